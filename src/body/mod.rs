@@ -0,0 +1,283 @@
+//! Tower <-> hyper body utilities
+
+use bytes::Bytes;
+use futures::{try_ready, Async, Poll};
+use http_body::Body as HttpBody;
+use hyper::body::Payload;
+use hyper::Chunk;
+use std::fmt;
+use std::io::Cursor;
+use tokio_buf::SizeHint;
+
+mod replay;
+
+pub use self::replay::{ReplayError, Replayable};
+pub use hyper::Body;
+
+/// The error type of a `BoxBody`.
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Lifts a body to support `Payload`
+#[derive(Debug)]
+pub struct LiftBody<T> {
+    inner: T,
+}
+
+/// Lifts a body with `Bytes` data to support `Payload`
+///
+/// Bodies built on `Bytes` yield their data as a `Cursor<Bytes>`, which
+/// `LiftBody` hands to hyper as an opaque `Buf`. Code that needs to own that
+/// data, such as forwarding it into a `hyper::Body` channel, has to copy it
+/// out of the `Buf`. `LiftBytes` instead slices each cursor into a `Chunk`
+/// that shares its buffer, so the data can be owned without copying.
+///
+/// This does not make sending a body cheaper: when the IO supports vectored
+/// writes, hyper writes the data of both `LiftBody` and `LiftBytes` without
+/// copying it.
+///
+/// `Connection`, `Client` and `Server` always lift bodies with `LiftBody`,
+/// so `LiftBytes` can only be used with hyper's own `client::conn` and
+/// `server::conn` APIs.
+#[derive(Debug)]
+pub struct LiftBytes<T> {
+    inner: T,
+}
+
+/// A type-erased body with `Chunk` data and a boxed error.
+///
+/// The data is a `Chunk` rather than `Bytes`, as `Bytes` does not implement
+/// `Buf` in bytes 0.4 and so cannot be body data. A `Chunk` converts to and
+/// from `Bytes` without copying.
+///
+/// Middleware stacks often produce several body types, while `Connection`,
+/// `Client` and `Server` each need a single concrete body type. Erasing them
+/// into a `BoxBody` lets one stack serve them all, both as request and as
+/// response body.
+///
+/// # Example
+///
+/// ```
+/// # use tower_hyper::body::{Body, BoxBody};
+/// let body = BoxBody::new(Body::from("hello world"));
+/// let empty = BoxBody::empty();
+/// ```
+pub struct BoxBody {
+    inner: Box<dyn HttpBody<Data = Chunk, Error = BoxError> + Send>,
+}
+
+/// Maps the data and errors of a body for `BoxBody`.
+struct Map<B, D, E> {
+    inner: B,
+    data: D,
+    err: E,
+}
+
+impl<T: HttpBody> From<T> for LiftBody<T> {
+    fn from(inner: T) -> Self {
+        LiftBody { inner }
+    }
+}
+
+impl<T> Payload for LiftBody<T>
+where
+    T: HttpBody + Send + 'static,
+    T::Data: Send,
+    T::Error: Into<crate::Error>,
+{
+    type Data = T::Data;
+    type Error = T::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        self.inner.poll_data()
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<hyper::HeaderMap>, Self::Error> {
+        self.inner.poll_trailers()
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        exact_length(self.inner.size_hint())
+    }
+}
+
+// ===== impl LiftBytes =====
+
+impl<T> From<T> for LiftBytes<T>
+where
+    T: HttpBody<Data = Cursor<Bytes>>,
+{
+    fn from(inner: T) -> Self {
+        LiftBytes { inner }
+    }
+}
+
+impl<T> Payload for LiftBytes<T>
+where
+    T: HttpBody<Data = Cursor<Bytes>> + Send + 'static,
+    T::Error: Into<crate::Error>,
+{
+    type Data = Chunk;
+    type Error = T::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        let data = try_ready!(self.inner.poll_data());
+        Ok(Async::Ready(data.map(|cursor| {
+            // Skip what was already consumed from the cursor by slicing the
+            // shared buffer, rather than copying the remainder.
+            let pos = cursor.position() as usize;
+            let mut bytes = cursor.into_inner();
+            bytes.advance(pos);
+            Chunk::from(bytes)
+        })))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<hyper::HeaderMap>, Self::Error> {
+        self.inner.poll_trailers()
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        exact_length(self.inner.size_hint())
+    }
+}
+
+fn exact_length(hint: SizeHint) -> Option<u64> {
+    match hint.upper() {
+        Some(upper) if upper == hint.lower() => Some(upper),
+        _ => None,
+    }
+}
+
+// ===== impl BoxBody =====
+
+impl BoxBody {
+    /// Erase `body`, converting its data into a `Chunk` and its error into a
+    /// boxed error.
+    pub fn new<B>(body: B) -> Self
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Into<Chunk>,
+        B::Error: Into<BoxError>,
+    {
+        BoxBody::map(body, Into::into, Into::into)
+    }
+
+    /// Create an empty body.
+    pub fn empty() -> Self {
+        BoxBody::new(Body::empty())
+    }
+
+    /// Erase `body`, converting its data into a `Chunk` with `f`.
+    ///
+    /// This is useful for bodies whose data does not convert into a `Chunk`.
+    pub fn map_data<B, F>(body: B, f: F) -> Self
+    where
+        B: HttpBody + Send + 'static,
+        B::Error: Into<BoxError>,
+        F: FnMut(B::Data) -> Chunk + Send + 'static,
+    {
+        BoxBody::map(body, f, Into::into)
+    }
+
+    /// Erase `body`, converting its error into a boxed error with `f`.
+    ///
+    /// This is useful for bodies whose error does not convert into a boxed
+    /// error.
+    pub fn map_err<B, F>(body: B, f: F) -> Self
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Into<Chunk>,
+        F: FnMut(B::Error) -> BoxError + Send + 'static,
+    {
+        BoxBody::map(body, Into::into, f)
+    }
+
+    fn map<B, D, E>(inner: B, data: D, err: E) -> Self
+    where
+        B: HttpBody + Send + 'static,
+        D: FnMut(B::Data) -> Chunk + Send + 'static,
+        E: FnMut(B::Error) -> BoxError + Send + 'static,
+    {
+        BoxBody {
+            inner: Box::new(Map { inner, data, err }),
+        }
+    }
+}
+
+impl HttpBody for BoxBody {
+    type Data = Chunk;
+    type Error = BoxError;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        self.inner.poll_data()
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<hyper::HeaderMap>, Self::Error> {
+        self.inner.poll_trailers()
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl From<Body> for BoxBody {
+    fn from(body: Body) -> Self {
+        BoxBody::new(body)
+    }
+}
+
+impl Default for BoxBody {
+    fn default() -> Self {
+        BoxBody::empty()
+    }
+}
+
+impl fmt::Debug for BoxBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BoxBody").finish()
+    }
+}
+
+// ===== impl Map =====
+
+impl<B, D, E> HttpBody for Map<B, D, E>
+where
+    B: HttpBody,
+    D: FnMut(B::Data) -> Chunk,
+    E: FnMut(B::Error) -> BoxError,
+{
+    type Data = Chunk;
+    type Error = BoxError;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        match self.inner.poll_data() {
+            Ok(Async::Ready(data)) => Ok(Async::Ready(data.map(&mut self.data))),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(e) => Err((self.err)(e)),
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<hyper::HeaderMap>, Self::Error> {
+        self.inner.poll_trailers().map_err(&mut self.err)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
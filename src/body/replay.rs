@@ -0,0 +1,215 @@
+use bytes::Bytes;
+use futures::{Async, Poll};
+use http_body::Body as HttpBody;
+use hyper::{Chunk, HeaderMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tokio_buf::SizeHint;
+
+/// A body that buffers its data while streamed, so it can be sent again.
+///
+/// Requests with streaming bodies can not be cloned, which retry middleware
+/// needs to send a request more than once. A `Replayable` body keeps a copy
+/// of every chunk it streams, up to a limit, and `replay` creates a fresh
+/// body that yields the buffered chunks before continuing with the rest of
+/// the original body.
+///
+/// Once more than `limit` bytes were streamed, the buffer is dropped and the
+/// body can no longer be replayed. Copies that still need the dropped chunks
+/// fail with a [`ReplayError`].
+///
+/// Copies share the original body, and are meant to be polled one after
+/// another, like the attempts of a retried request.
+///
+/// # Example
+///
+/// ```
+/// # use tower_hyper::body::{Body, Replayable};
+/// let body = Replayable::new(Body::from("hello world"), 64 * 1024);
+/// let retry = body.replay().expect("nothing streamed yet");
+/// ```
+///
+/// [`ReplayError`]: ./struct.ReplayError.html
+#[derive(Debug)]
+pub struct Replayable<B> {
+    shared: Arc<Mutex<Shared<B>>>,
+    /// The number of chunks this copy has yielded.
+    pos: usize,
+    size_hint: SizeHint,
+}
+
+/// Error produced when a `Replayable` copy needs data that was not buffered.
+#[derive(Debug)]
+pub struct ReplayError {
+    limit: usize,
+}
+
+#[derive(Debug)]
+struct Shared<B> {
+    inner: B,
+    /// Every chunk read from `inner` so far, until the limit is exceeded.
+    ///
+    /// Kept as `Bytes`, as `Chunk` can not be cloned.
+    buffer: Vec<Bytes>,
+    /// The number of chunks read from `inner`.
+    read: usize,
+    buffered_len: usize,
+    limit: usize,
+    overflowed: bool,
+    /// Whether `inner` yielded all its data, after which it must not be
+    /// polled for data again.
+    ended: bool,
+    /// The result of polling the trailers of `inner`, once ready.
+    trailers: Option<Option<HeaderMap>>,
+}
+
+// ===== impl Replayable =====
+
+impl<B> Replayable<B>
+where
+    B: HttpBody,
+{
+    /// Wrap `body`, buffering up to `limit` bytes of it for replays.
+    pub fn new(body: B, limit: usize) -> Self {
+        let size_hint = body.size_hint();
+        let shared = Shared {
+            inner: body,
+            buffer: Vec::new(),
+            read: 0,
+            buffered_len: 0,
+            limit,
+            overflowed: false,
+            ended: false,
+            trailers: None,
+        };
+
+        Replayable {
+            shared: Arc::new(Mutex::new(shared)),
+            pos: 0,
+            size_hint,
+        }
+    }
+}
+
+impl<B> Replayable<B> {
+    /// Create a copy of the body that starts from the beginning.
+    ///
+    /// Returns `None` if more than the limit was already streamed.
+    pub fn replay(&self) -> Option<Self> {
+        if !self.is_replayable() {
+            return None;
+        }
+
+        Some(Replayable {
+            shared: self.shared.clone(),
+            pos: 0,
+            size_hint: self.size_hint.clone(),
+        })
+    }
+
+    /// Returns `true` if the streamed data still fits within the limit.
+    pub fn is_replayable(&self) -> bool {
+        !self.shared.lock().unwrap().overflowed
+    }
+}
+
+impl<B> HttpBody for Replayable<B>
+where
+    B: HttpBody,
+    B::Data: Into<Chunk>,
+    B::Error: Into<crate::Error>,
+{
+    type Data = Chunk;
+    type Error = crate::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        let mut shared = self.shared.lock().unwrap();
+
+        // Copies behind the original replay from the buffer.
+        if self.pos < shared.read {
+            if shared.overflowed {
+                let limit = shared.limit;
+                return Err(Box::new(ReplayError { limit }));
+            }
+
+            let chunk = shared.buffer[self.pos].clone();
+            self.pos += 1;
+            return Ok(Async::Ready(Some(Chunk::from(chunk))));
+        }
+
+        if shared.ended {
+            return Ok(Async::Ready(None));
+        }
+
+        let chunk: Bytes = match shared.inner.poll_data() {
+            Ok(Async::Ready(Some(data))) => data.into().into_bytes(),
+            Ok(Async::Ready(None)) => {
+                shared.ended = true;
+                return Ok(Async::Ready(None));
+            }
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(e) => return Err(e.into()),
+        };
+
+        shared.read += 1;
+        self.pos += 1;
+
+        if !shared.overflowed {
+            shared.buffered_len += chunk.len();
+            if shared.buffered_len > shared.limit {
+                shared.overflowed = true;
+                shared.buffer = Vec::new();
+            } else {
+                shared.buffer.push(chunk.clone());
+            }
+        }
+
+        Ok(Async::Ready(Some(Chunk::from(chunk))))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        let mut shared = self.shared.lock().unwrap();
+
+        if let Some(trailers) = &shared.trailers {
+            return Ok(Async::Ready(trailers.clone()));
+        }
+
+        let trailers = match shared.inner.poll_trailers() {
+            Ok(Async::Ready(trailers)) => trailers,
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(e) => return Err(e.into()),
+        };
+        shared.trailers = Some(trailers.clone());
+        Ok(Async::Ready(trailers))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        let shared = self.shared.lock().unwrap();
+        self.pos == shared.read && (shared.ended || shared.inner.is_end_stream())
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.size_hint.clone()
+    }
+}
+
+// ===== impl ReplayError =====
+
+impl ReplayError {
+    /// The maximum number of bytes that could be buffered for replays.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "body can not be replayed after exceeding the buffer limit of {} bytes",
+            self.limit
+        )
+    }
+}
+
+impl std::error::Error for ReplayError {}
@@ -1,5 +1,10 @@
+use super::response_head::{HeadTimeout, LivenessIo};
+use super::Error;
+use super::ResponseHeadTimedOut;
 use crate::body::LiftBody;
-use futures::{Future, Poll};
+use crate::trace::Span;
+use futures::sync::oneshot;
+use futures::{Async, Future, Poll};
 use http_body::Body as HttpBody;
 use hyper::client::conn::Connection as HyperConnection;
 use log::debug;
@@ -18,21 +23,38 @@ where
     B::Data: Send,
     B::Error: Into<crate::Error>,
 {
-    connection: HyperConnection<T, LiftBody<B>>,
-    handle: Handle,
+    connection: HyperConnection<LivenessIo<T>, LiftBody<B>>,
+    head_timeout: Option<HeadTimeout>,
+    /// Set once the response head timed out, and reported instead of the
+    /// error it causes in hyper.
+    timed_out: Option<ResponseHeadTimedOut>,
+    error: Arc<Mutex<Option<Error>>>,
+    /// Dropped with the task, which notifies the handle.
+    _done: oneshot::Sender<()>,
+    span: Span,
 }
 
-/// Shared handle between background task and connection
-#[derive(Clone, Debug, Default)]
+/// Handle the connection holds on to its background task
+#[derive(Debug)]
 pub(super) struct Handle {
     /// Errors encountered by the background task
-    error: Arc<Mutex<Option<hyper::Error>>>,
+    error: Arc<Mutex<Option<Error>>>,
+    done: oneshot::Receiver<()>,
 }
 
 impl Handle {
-    pub(super) fn get_error(&self) -> Option<hyper::Error> {
+    pub(super) fn get_error(&self) -> Option<Error> {
         self.error.try_lock().ok().and_then(|mut err| err.take())
     }
+
+    /// Poll for the background task to end.
+    pub(super) fn poll_done(&mut self) -> Async<()> {
+        match self.done.poll() {
+            Ok(Async::NotReady) => Async::NotReady,
+            // The sender is never used, only dropped.
+            Ok(Async::Ready(())) | Err(oneshot::Canceled) => Async::Ready(()),
+        }
+    }
 }
 
 impl<T, B> Background<T, B>
@@ -42,14 +64,30 @@ where
     B::Data: Send,
     B::Error: Into<crate::Error>,
 {
-    pub(super) fn new(connection: HyperConnection<T, LiftBody<B>>) -> (Self, Handle) {
-        let handle = Handle::default();
+    pub(super) fn new(
+        connection: HyperConnection<LivenessIo<T>, LiftBody<B>>,
+        head_timeout: Option<HeadTimeout>,
+        span: Span,
+    ) -> (Self, Handle) {
+        let error = Arc::new(Mutex::new(None));
+        let (tx, rx) = oneshot::channel();
         let bg = Background {
             connection,
-            handle: handle.clone(),
+            head_timeout,
+            timed_out: None,
+            error: error.clone(),
+            _done: tx,
+            span,
         };
+        let handle = Handle { error, done: rx };
         (bg, handle)
     }
+
+    fn set_error(&self, error: crate::Error) {
+        if let Ok(mut l) = self.error.try_lock() {
+            *l = Some(Error::background(error));
+        }
+    }
 }
 
 impl<T, B> Future for Background<T, B>
@@ -63,14 +101,57 @@ where
     type Error = ();
 
     fn poll(&mut self) -> Poll<(), ()> {
-        self.connection.poll().map_err(|e| {
-            // errors are tracked by the handle, so lowering this
-            // serverity to debug.
-            debug!("error with hyper: {}", e);
-            if let Ok(mut l) = self.handle.error.try_lock() {
-                *l = Some(e);
+        let _enter = self.span.enter();
+
+        loop {
+            let done = match self.connection.poll() {
+                Ok(done) => done,
+                Err(e) => {
+                    // errors are tracked by the handle, so lowering this
+                    // serverity to debug.
+                    debug!("error with hyper: {}", e);
+                    event!(error = %e, "connection error");
+                    match self.timed_out.take() {
+                        Some(timed_out) => self.set_error(Box::new(timed_out)),
+                        None => self.set_error(Box::new(e)),
+                    }
+                    return Err(());
+                }
+            };
+
+            if let Async::Ready(()) = done {
+                // hyper may close the connection after failing the requests
+                // in flight, without returning the error.
+                if let Some(e) = self.timed_out.take() {
+                    self.set_error(Box::new(e));
+                }
+                return Ok(Async::Ready(()));
+            }
+
+            if let Some(e) = self.timed_out.take() {
+                // hyper did not read from the expired IO, so it never saw
+                // the timeout. Give up on the connection.
+                self.set_error(Box::new(e));
+                return Err(());
+            }
+
+            let expired = match &mut self.head_timeout {
+                Some(head_timeout) => head_timeout.poll_expired(),
+                None => Async::NotReady,
+            };
+
+            match expired {
+                Async::Ready(e) => {
+                    debug!("{}", e);
+                    event!("response head timed out");
+                    // Reads now fail, poll the connection again so hyper
+                    // fails the requests in flight.
+                    self.head_timeout = None;
+                    self.timed_out = Some(e);
+                }
+                Async::NotReady => return Ok(Async::NotReady),
             }
-        })
+        }
     }
 }
 
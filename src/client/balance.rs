@@ -0,0 +1,337 @@
+use super::{Connect, ConnectDiscover, ConnectExecutor, Connection, Error, ResponseFuture};
+use crate::body::Body;
+use futures::stream::{self, IterOk};
+use futures::{task, Async, Future, Poll, Stream};
+use http::{Request, Response};
+use http_body::Body as HttpBody;
+use hyper::client::conn;
+use log::debug;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::Arc;
+use std::vec;
+use tower_discover::Discover;
+use tower_http_util::connection::HttpMakeConnection;
+use tower_service::Service;
+
+/// A change to the set of endpoints of a `Balance`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change<A> {
+    /// Connect to a new endpoint.
+    Insert(A),
+    /// Stop sending requests to an endpoint.
+    Remove(A),
+}
+
+/// Balances requests across connections to a set of endpoints.
+///
+/// Each endpoint target is connected to with a `Connect`. For every request,
+/// two ready endpoints are picked at random and the one with fewer pending
+/// requests is used, the "power of two choices". This keeps the load even
+/// without tracking every endpoint precisely. A request is pending until its
+/// response head is received, streaming its body does not count.
+///
+/// Endpoints are added and removed by a `Stream` of [`Change`]s, and
+/// connected to by a [`ConnectDiscover`]. Endpoints that fail to connect, or
+/// whose connection fails, are removed; insert them again to retry.
+/// Connections closed by the peer, or retired by [`Connect::max_lifetime`]
+/// or [`Connect::max_requests`], are replaced by a new connection to the
+/// same endpoint.
+///
+/// [`Change`]: ./enum.Change.html
+/// [`ConnectDiscover`]: ./struct.ConnectDiscover.html
+/// [`Connect::max_lifetime`]: ./struct.Connect.html#method.max_lifetime
+/// [`Connect::max_requests`]: ./struct.Connect.html#method.max_requests
+pub struct Balance<St, A, B, C, E>
+where
+    B: HttpBody,
+    C: HttpMakeConnection<A>,
+{
+    discover: ConnectDiscover<St, A, B, C, E>,
+    endpoints: Vec<Endpoint<A, B>>,
+    /// The endpoint chosen by `poll_ready`.
+    chosen: Option<usize>,
+    rng: Rng,
+}
+
+/// The future returned by `Balance`.
+#[derive(Debug)]
+pub struct BalanceFuture {
+    inner: ResponseFuture<conn::ResponseFuture>,
+    /// Counts as a pending request of the endpoint until dropped.
+    _pending: Arc<()>,
+}
+
+/// The error returned by `Balance` once it has no endpoints left, and can
+/// not get new ones.
+#[derive(Debug)]
+pub struct NoEndpoints {
+    _p: (),
+}
+
+struct Endpoint<A, B>
+where
+    B: HttpBody,
+{
+    target: A,
+    connection: Connection<B>,
+    /// Cloned into every response future, so the strong count tracks the
+    /// pending requests.
+    pending: Arc<()>,
+}
+
+/// A xorshift generator, good enough to pick endpoints.
+#[derive(Debug)]
+struct Rng(u64);
+
+// ===== impl Balance =====
+
+impl<A, B, C, E> Balance<IterOk<vec::IntoIter<Change<A>>, crate::Error>, A, B, C, E>
+where
+    A: Hash + Eq,
+    B: HttpBody,
+    C: HttpMakeConnection<A>,
+{
+    /// Balance requests across a fixed set of endpoint targets.
+    pub fn from_targets<I>(connect: Connect<A, B, C, E>, targets: I) -> Self
+    where
+        I: IntoIterator<Item = A>,
+    {
+        let changes: Vec<_> = targets.into_iter().map(Change::Insert).collect();
+        Balance::new(connect, stream::iter_ok(changes))
+    }
+}
+
+impl<St, A, B, C, E> Balance<St, A, B, C, E>
+where
+    A: Hash + Eq,
+    B: HttpBody,
+    C: HttpMakeConnection<A>,
+{
+    /// Balance requests across the endpoint targets inserted by `changes`.
+    pub fn new(connect: Connect<A, B, C, E>, changes: St) -> Self {
+        Balance {
+            discover: ConnectDiscover::new(connect, changes),
+            endpoints: Vec::new(),
+            chosen: None,
+            rng: Rng::new(),
+        }
+    }
+
+    /// The number of endpoints, whether they are connected or not.
+    pub fn len(&self) -> usize {
+        self.endpoints.len() + self.discover.connecting()
+    }
+
+    /// Returns `true` if there are no endpoints.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<St, A, B, C, E> Balance<St, A, B, C, E>
+where
+    St: Stream<Item = Change<A>>,
+    St::Error: Into<crate::Error>,
+    A: Clone + Hash + Eq,
+    C: HttpMakeConnection<A> + 'static,
+    C::Error: fmt::Display,
+    C::Connection: Send + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<crate::Error>,
+    E: ConnectExecutor<C::Connection, B> + Clone,
+{
+    /// Apply the endpoints discovered so far.
+    fn poll_discover(&mut self) -> Result<(), crate::Error> {
+        loop {
+            match self.discover.poll() {
+                Ok(Async::Ready(tower_discover::Change::Insert(target, connection))) => {
+                    self.endpoints.push(Endpoint {
+                        target,
+                        connection,
+                        pending: Arc::new(()),
+                    });
+                }
+                Ok(Async::Ready(tower_discover::Change::Remove(target))) => {
+                    // Dropping the connection closes it once its requests
+                    // in flight are done.
+                    self.endpoints.retain(|e| e.target != target);
+                }
+                Ok(Async::NotReady) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Poll the connection of every endpoint, returning the indices of the
+    /// ready ones.
+    fn poll_endpoints(&mut self) -> Vec<usize> {
+        let mut ready = Vec::new();
+        let mut i = 0;
+
+        while i < self.endpoints.len() {
+            match self.endpoints[i].connection.poll_ready() {
+                Ok(Async::Ready(())) => {
+                    ready.push(i);
+                    i += 1;
+                }
+                Ok(Async::NotReady) => i += 1,
+                Err(Error::Retired(_)) | Err(Error::ConnectionClosed(_)) => {
+                    // The connection closed gracefully, the endpoint itself
+                    // did not fail.
+                    let endpoint = self.endpoints.swap_remove(i);
+                    self.discover.reconnect(endpoint.target);
+                    // Poll again to start connecting.
+                    task::current().notify();
+                }
+                Err(e) => {
+                    debug!("endpoint connection error: {}", e);
+                    let endpoint = self.endpoints.swap_remove(i);
+                    self.discover.forget(&endpoint.target);
+                }
+            }
+        }
+
+        ready
+    }
+
+    /// Pick the ready endpoint with fewer pending requests out of two random
+    /// ones.
+    fn choose(&mut self, ready: &[usize]) -> usize {
+        if ready.len() == 1 {
+            return ready[0];
+        }
+
+        let a = self.rng.below(ready.len());
+        // Pick a different second endpoint.
+        let b = (a + 1 + self.rng.below(ready.len() - 1)) % ready.len();
+
+        let (a, b) = (ready[a], ready[b]);
+        if self.endpoints[a].pending() <= self.endpoints[b].pending() {
+            a
+        } else {
+            b
+        }
+    }
+}
+
+impl<St, A, B, C, E> Service<Request<B>> for Balance<St, A, B, C, E>
+where
+    St: Stream<Item = Change<A>>,
+    St::Error: Into<crate::Error>,
+    A: Clone + Hash + Eq,
+    C: HttpMakeConnection<A> + 'static,
+    C::Error: fmt::Display,
+    C::Connection: Send + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<crate::Error>,
+    E: ConnectExecutor<C::Connection, B> + Clone,
+{
+    type Response = Response<Body>;
+    type Error = crate::Error;
+    type Future = BalanceFuture;
+
+    /// Ready once any endpoint is ready.
+    ///
+    /// Without endpoints, this is not ready until one is inserted. Once the
+    /// `Stream` of changes ended, this fails with [`NoEndpoints`] if no
+    /// endpoint is left.
+    ///
+    /// [`NoEndpoints`]: ./struct.NoEndpoints.html
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.poll_discover()?;
+
+        let ready = self.poll_endpoints();
+        if ready.is_empty() {
+            self.chosen = None;
+            if self.endpoints.is_empty() && self.discover.is_exhausted() {
+                return Err(Box::new(NoEndpoints { _p: () }));
+            }
+            return Ok(Async::NotReady);
+        }
+
+        self.chosen = Some(self.choose(&ready));
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let i = self.chosen.take().expect("called before ready");
+        let endpoint = &mut self.endpoints[i];
+
+        BalanceFuture {
+            inner: endpoint.connection.call(req),
+            _pending: endpoint.pending.clone(),
+        }
+    }
+}
+
+impl<St, A, B, C, E> fmt::Debug for Balance<St, A, B, C, E>
+where
+    B: HttpBody,
+    C: HttpMakeConnection<A>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Balance")
+            .field("endpoints", &self.endpoints.len())
+            .finish()
+    }
+}
+
+// ===== impl BalanceFuture =====
+
+impl Future for BalanceFuture {
+    type Item = Response<Body>;
+    type Error = crate::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.inner.poll() {
+            Ok(Async::Ready(response)) => Ok(Async::Ready(response)),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+// ===== impl NoEndpoints =====
+
+impl fmt::Display for NoEndpoints {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("no endpoints left to balance requests across")
+    }
+}
+
+impl std::error::Error for NoEndpoints {}
+
+// ===== impl Endpoint =====
+
+impl<A, B> Endpoint<A, B>
+where
+    B: HttpBody,
+{
+    fn pending(&self) -> usize {
+        Arc::strong_count(&self.pending) - 1
+    }
+}
+
+// ===== impl Rng =====
+
+impl Rng {
+    fn new() -> Self {
+        // Seed from the randomly keyed std hasher, and never with zero.
+        let seed = RandomState::new().build_hasher().finish();
+        Rng(seed | 1)
+    }
+
+    /// A number in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        (x % n as u64) as usize
+    }
+}
@@ -1,5 +1,8 @@
+use super::connection::Limits;
+use super::response_head::{HeadTimeout, Liveness, LivenessIo};
 use super::{background::Background, Connection};
 use crate::body::LiftBody;
+use crate::trace::Span;
 use futures::{try_ready, Async, Future, Poll};
 use http::Version;
 use http_body::Body as HttpBody;
@@ -8,6 +11,7 @@ use hyper::client::conn::{Builder, Handshake};
 use hyper::Error;
 use std::fmt;
 use std::marker::PhantomData;
+use std::time::Duration;
 use tokio_executor::{DefaultExecutor, TypedExecutor};
 use tokio_io::{AsyncRead, AsyncWrite};
 use tower_http_util::connection::HttpMakeConnection;
@@ -23,6 +27,8 @@ pub struct Connect<A, B, C, E> {
     inner: C,
     builder: Builder,
     exec: E,
+    response_head_timeout: Option<Duration>,
+    limits: Limits,
     _pd: PhantomData<(A, B)>,
 }
 
@@ -45,6 +51,9 @@ where
     state: State<A, B, C>,
     builder: Builder,
     exec: E,
+    response_head_timeout: Option<Duration>,
+    limits: Limits,
+    span: Span,
 }
 
 enum State<A, B, C>
@@ -53,7 +62,12 @@ where
     C: HttpMakeConnection<A>,
 {
     Connect(C::Future),
-    Handshake(Handshake<C::Connection, LiftBody<B>>),
+    /// Also keeps whether the transport negotiated HTTP/2.
+    Handshake(
+        Handshake<LivenessIo<C::Connection>, LiftBody<B>>,
+        Liveness,
+        bool,
+    ),
 }
 
 /// The error produced from creating a connection
@@ -124,9 +138,65 @@ where
             inner,
             builder,
             exec,
+            response_head_timeout: None,
+            limits: Limits::default(),
             _pd: PhantomData,
         }
     }
+
+    /// Fail connections that read nothing from their peer for `timeout`
+    /// while a request waits for its response head.
+    ///
+    /// The timer restarts whenever bytes are read, so a slow response head
+    /// that keeps arriving does not time out. Once it fires, the requests
+    /// in flight fail with `Error::Timeout`, and the background task ends
+    /// with a [`ResponseHeadTimedOut`] error, which `Connection::poll_ready`
+    /// returns so that pools can evict the connection.
+    ///
+    /// This is not a keep-alive check: a request is in flight from `call`
+    /// until its response head arrives, so neither idle connections nor
+    /// stalled response bodies are checked. Pick a timeout longer than the
+    /// slowest expected response.
+    ///
+    /// By default connections are not checked.
+    ///
+    /// [`ResponseHeadTimedOut`]: ./struct.ResponseHeadTimedOut.html
+    pub fn response_head_timeout(mut self, timeout: Duration) -> Self {
+        self.response_head_timeout = Some(timeout);
+        self
+    }
+
+    /// Retire connections once they are older than `lifetime`.
+    ///
+    /// Retired connections close gracefully and fail `poll_ready` with
+    /// [`Error::Retired`], see [`Connection::is_retired`]. This spreads
+    /// long-lived clients across the backends of a load balancer that
+    /// balances connections rather than requests. A timer wakes the task
+    /// polling the connection once the lifetime is reached, so idle
+    /// connections are retired too.
+    ///
+    /// By default connections are not retired.
+    ///
+    /// [`Error::Retired`]: ./enum.Error.html#variant.Retired
+    /// [`Connection::is_retired`]: ./struct.Connection.html#method.is_retired
+    pub fn max_lifetime(mut self, lifetime: Duration) -> Self {
+        self.limits.max_lifetime = Some(lifetime);
+        self
+    }
+
+    /// Retire connections once they sent `max` requests.
+    ///
+    /// Retired connections close gracefully and fail `poll_ready` with
+    /// [`Error::Retired`], see [`Connection::is_retired`].
+    ///
+    /// By default connections are not retired.
+    ///
+    /// [`Error::Retired`]: ./enum.Error.html#variant.Retired
+    /// [`Connection::is_retired`]: ./struct.Connection.html#method.is_retired
+    pub fn max_requests(mut self, max: usize) -> Self {
+        self.limits.max_requests = Some(max);
+        self
+    }
 }
 
 impl<A, B, C, E> Service<A> for Connect<A, B, C, E>
@@ -152,11 +222,17 @@ where
         let state = State::Connect(self.inner.make_connection(target));
         let builder = self.builder.clone();
         let exec = self.exec.clone();
+        let response_head_timeout = self.response_head_timeout;
+        let limits = self.limits;
+        let span = span!("connect");
 
         ConnectFuture {
             state,
             builder,
             exec,
+            response_head_timeout,
+            limits,
+            span,
         }
     }
 }
@@ -176,20 +252,34 @@ where
     type Error = ConnectError<C::Error>;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let _enter = self.span.enter();
+
         loop {
             let io = match self.state {
                 State::Connect(ref mut fut) => {
-                    let res = fut.poll().map_err(ConnectError::Connect);
+                    let res = fut.poll().map_err(|e| {
+                        event!("error establishing connection");
+                        ConnectError::Connect(e)
+                    });
 
                     try_ready!(res)
                 }
-                State::Handshake(ref mut fut) => {
-                    let (sender, conn) = try_ready!(fut.poll().map_err(ConnectError::Handshake));
+                State::Handshake(ref mut fut, ref liveness, http2) => {
+                    let (sender, conn) = try_ready!(fut.poll().map_err(|e| {
+                        event!(error = %e, "handshake error");
+                        ConnectError::Handshake(e)
+                    }));
 
-                    let (bg, handle) = Background::new(conn);
+                    event!("connection established");
+
+                    let head_timeout = self
+                        .response_head_timeout
+                        .map(|timeout| HeadTimeout::new(liveness.clone(), timeout));
+                    let liveness = head_timeout.as_ref().map(|_| liveness.clone());
+                    let (bg, handle) = Background::new(conn, head_timeout, self.span.clone());
                     self.exec.spawn(bg).map_err(|_| ConnectError::SpawnError)?;
 
-                    let connection = Connection::new(sender, handle);
+                    let connection = Connection::new(sender, handle, self.limits, liveness, http2);
 
                     return Ok(Async::Ready(connection));
                 }
@@ -197,13 +287,18 @@ where
 
             let mut builder = self.builder.clone();
 
-            if let Some(Version::HTTP_2) = io.negotiated_version() {
+            let version = io.negotiated_version();
+            event!(version = ?version, "negotiated version");
+
+            let http2 = version == Some(Version::HTTP_2);
+            if http2 {
                 builder.http2_only(true);
             }
 
-            let handshake = builder.handshake(io);
+            let liveness = Liveness::new();
+            let handshake = builder.handshake(LivenessIo::new(io, liveness.clone()));
 
-            self.state = State::Handshake(handshake);
+            self.state = State::Handshake(handshake, liveness, http2);
         }
     }
 }
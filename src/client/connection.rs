@@ -1,10 +1,14 @@
 use super::background::Handle;
-use super::ResponseFuture;
+use super::response_head::Liveness;
+use super::{future, Error, ResponseFuture};
 use crate::body::{Body, LiftBody};
-use futures::Poll;
+use futures::{Async, Future, Poll};
 use http::{Request, Response};
 use http_body::Body as HttpBody;
 use hyper::client::conn;
+use log::debug;
+use std::time::Duration;
+use tokio_timer::{clock, Delay};
 use tower_service::Service;
 
 /// The connection provided from `hyper`
@@ -17,7 +21,32 @@ pub struct Connection<B>
 where
     B: HttpBody,
 {
-    sender: conn::SendRequest<LiftBody<B>>,
+    /// Dropped once the connection is retired.
+    sender: Option<conn::SendRequest<LiftBody<B>>>,
+    handle: Handle,
+    /// Fires when the connection exceeds its maximum lifetime, waking the
+    /// task that polls it so that idle connections are retired too.
+    expires: Option<Delay>,
+    /// The number of requests left before the connection is retired.
+    remaining: Option<usize>,
+    /// Set when the connection has a response head timeout.
+    liveness: Option<Liveness>,
+    /// Set when the transport negotiated HTTP/2.
+    http2: bool,
+}
+
+/// Limits after which a connection is retired.
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct Limits {
+    pub(super) max_lifetime: Option<Duration>,
+    pub(super) max_requests: Option<usize>,
+}
+
+/// A future that resolves once a closed `Connection` has shut down.
+///
+/// Created by `Connection::close`.
+#[derive(Debug)]
+pub struct Close {
     handle: Handle,
 }
 
@@ -25,8 +54,76 @@ impl<B> Connection<B>
 where
     B: HttpBody,
 {
-    pub(super) fn new(sender: conn::SendRequest<LiftBody<B>>, handle: Handle) -> Self {
-        Connection { sender, handle }
+    pub(super) fn new(
+        sender: conn::SendRequest<LiftBody<B>>,
+        handle: Handle,
+        limits: Limits,
+        liveness: Option<Liveness>,
+        http2: bool,
+    ) -> Self {
+        Connection {
+            sender: Some(sender),
+            handle,
+            expires: limits
+                .max_lifetime
+                .map(|lifetime| Delay::new(clock::now() + lifetime)),
+            remaining: limits.max_requests,
+            liveness,
+            http2,
+        }
+    }
+
+    /// Returns `true` if the connection reached its maximum lifetime or
+    /// number of requests.
+    ///
+    /// Retired connections fail `poll_ready` with `Error::Retired`, and
+    /// should be discarded.
+    pub fn is_retired(&self) -> bool {
+        self.sender.is_none()
+    }
+
+    /// Returns `true` if the transport negotiated HTTP/2, see
+    /// `HttpConnection::negotiated_version`.
+    pub(super) fn is_http2(&self) -> bool {
+        self.http2
+    }
+
+    fn limit_reached(&mut self) -> bool {
+        if self.remaining == Some(0) {
+            return true;
+        }
+
+        match &mut self.expires {
+            Some(delay) => match delay.poll() {
+                Ok(Async::Ready(())) => true,
+                // The timer only fires on its next turn, even once the
+                // deadline passed.
+                Ok(Async::NotReady) => delay.deadline() <= clock::now(),
+                Err(e) => {
+                    // Without a timer, the lifetime is only checked when
+                    // the connection is polled.
+                    debug!("max lifetime timer error: {}", e);
+                    delay.deadline() <= clock::now()
+                }
+            },
+            None => false,
+        }
+    }
+
+    /// Close the connection gracefully.
+    ///
+    /// The connection stops accepting requests, while responses already in
+    /// flight are still received. Once they are done, HTTP/1 connections
+    /// are closed and HTTP/2 connections send a `GOAWAY` frame. The
+    /// returned future resolves when the background task of the connection
+    /// has ended, with the error that ended it, if any.
+    pub fn close(self) -> Close {
+        // Dropping the sender lets hyper shut the connection down once it
+        // has no more requests in flight.
+        drop(self.sender);
+        Close {
+            handle: self.handle,
+        }
     }
 }
 
@@ -37,18 +134,70 @@ where
     B::Error: Into<crate::Error>,
 {
     type Response = Response<Body>;
-    type Error = hyper::Error;
+    type Error = Error;
     type Future = ResponseFuture<conn::ResponseFuture>;
 
+    /// Once the connection reached a limit set on `Connect`, it is retired:
+    /// it closes gracefully, and fails with `Error::Retired` from then on.
     fn poll_ready(&mut self) -> Poll<(), Self::Error> {
         if let Some(e) = self.handle.get_error() {
             return Err(e);
         }
-        self.sender.poll_ready()
+
+        if self.sender.is_some() && self.limit_reached() {
+            event!("connection retired");
+            // Dropping the sender closes the connection once the requests
+            // in flight are done.
+            self.sender = None;
+        }
+
+        match &mut self.sender {
+            Some(sender) => sender.poll_ready().map_err(Into::into),
+            None => Err(Error::retired()),
+        }
     }
 
     fn call(&mut self, req: Request<B>) -> Self::Future {
-        let inner = self.sender.send_request(req.map(LiftBody::from));
-        ResponseFuture { inner }
+        let sender = self
+            .sender
+            .as_mut()
+            .expect("Connection::call after the connection was retired");
+
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.saturating_sub(1);
+        }
+
+        // Count the request before sending it, which wakes the background
+        // task to arm the response head timer.
+        let in_flight = self.liveness.as_ref().map(Liveness::request);
+
+        let span = future::request_span(&req);
+        let inner = {
+            let _enter = span.enter();
+            sender.send_request(req.map(LiftBody::from))
+        };
+        ResponseFuture {
+            inner,
+            span,
+            in_flight,
+        }
+    }
+}
+
+// ===== impl Close =====
+
+impl Future for Close {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<(), Error> {
+        if let Async::NotReady = self.handle.poll_done() {
+            return Ok(Async::NotReady);
+        }
+
+        match self.handle.get_error() {
+            Some(e) => Err(e),
+            None => Ok(Async::Ready(())),
+        }
     }
 }
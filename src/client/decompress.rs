@@ -0,0 +1,463 @@
+use flate2::write::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH};
+use http::{HeaderMap, Method, Request, Response, StatusCode};
+use http_body::Body as HttpBody;
+use hyper::Chunk;
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+use tokio_buf::SizeHint;
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// The `Accept-Encoding` sent with requests that do not set one.
+const DEFAULT_ACCEPT_ENCODING: &str = "gzip, deflate, br";
+
+/// The buffer size of brotli decoders.
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+/// Decompresses response bodies of the inner service.
+///
+/// Requests without an `Accept-Encoding` header are sent with
+/// `Accept-Encoding: gzip, deflate, br`. Responses with a `gzip`, `deflate`
+/// or `br` `Content-Encoding` are decoded while they are streamed, and lose
+/// their `Content-Encoding` and `Content-Length` headers, which no longer
+/// describe the body. Other responses are passed through unchanged.
+///
+/// A `deflate` body is accepted both with and without the zlib header, as
+/// some servers send raw deflate data.
+///
+/// This requires the `compression` feature.
+#[derive(Clone, Debug)]
+pub struct Decompress<S> {
+    inner: S,
+    max_decoded_size: Option<usize>,
+}
+
+/// A `Layer` that wraps services in `Decompress`.
+#[derive(Clone, Debug, Default)]
+pub struct DecompressLayer {
+    max_decoded_size: Option<usize>,
+}
+
+/// The future returned by `Decompress`.
+#[derive(Debug)]
+pub struct DecompressFuture<F> {
+    inner: F,
+    /// Whether the response can have a body to decode.
+    has_body: bool,
+    max_decoded_size: Option<usize>,
+}
+
+/// A response body decoded by `Decompress`.
+#[derive(Debug)]
+pub struct DecompressBody<B> {
+    inner: B,
+    decoder: Option<Decoder>,
+}
+
+/// Error produced when a decoded response body exceeds the configured limit.
+///
+/// See [`Decompress::max_decoded_size`].
+///
+/// [`Decompress::max_decoded_size`]: ./struct.Decompress.html#method.max_decoded_size
+#[derive(Debug)]
+pub struct DecodedLengthError {
+    limit: usize,
+}
+
+enum Decoder {
+    Gzip(GzDecoder<Output>),
+    /// A `deflate` stream, until its first bytes tell whether it has a zlib
+    /// header.
+    Deflate(Vec<u8>, Output),
+    Zlib(ZlibDecoder<Output>),
+    RawDeflate(DeflateDecoder<Output>),
+    Brotli(Box<brotli::DecompressorWriter<Output>>),
+}
+
+/// The decoded output of a `Decoder`, bounded by an optional limit.
+struct Output {
+    buf: Vec<u8>,
+    /// The number of bytes that may still be decoded, if limited.
+    remaining: Option<usize>,
+    limit: usize,
+}
+
+// ===== impl Decompress =====
+
+impl<S> Decompress<S> {
+    /// Wrap `inner`, decompressing its response bodies.
+    pub fn new(inner: S) -> Self {
+        Decompress {
+            inner,
+            max_decoded_size: None,
+        }
+    }
+
+    /// Set the maximum size of a decoded response body, in bytes.
+    ///
+    /// A small compressed body can decode to a very large one. Bodies that
+    /// decode to more than `max` bytes fail with a [`DecodedLengthError`]
+    /// once the limit is reached. By default, decoded bodies are unlimited.
+    ///
+    /// [`DecodedLengthError`]: ./struct.DecodedLengthError.html
+    pub fn max_decoded_size(mut self, max: usize) -> Self {
+        self.max_decoded_size = Some(max);
+        self
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Decompress<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: HttpBody,
+{
+    type Response = Response<DecompressBody<ResBody>>;
+    type Error = S::Error;
+    type Future = DecompressFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        if !req.headers().contains_key(ACCEPT_ENCODING) {
+            let accept = HeaderValue::from_static(DEFAULT_ACCEPT_ENCODING);
+            req.headers_mut().insert(ACCEPT_ENCODING, accept);
+        }
+
+        DecompressFuture {
+            has_body: req.method() != Method::HEAD,
+            inner: self.inner.call(req),
+            max_decoded_size: self.max_decoded_size,
+        }
+    }
+}
+
+// ===== impl DecompressLayer =====
+
+impl DecompressLayer {
+    /// Create a new `DecompressLayer`.
+    pub fn new() -> Self {
+        DecompressLayer {
+            max_decoded_size: None,
+        }
+    }
+
+    /// Set the maximum size of a decoded response body, in bytes.
+    ///
+    /// See [`Decompress::max_decoded_size`].
+    ///
+    /// [`Decompress::max_decoded_size`]: ./struct.Decompress.html#method.max_decoded_size
+    pub fn max_decoded_size(mut self, max: usize) -> Self {
+        self.max_decoded_size = Some(max);
+        self
+    }
+}
+
+impl<S> Layer<S> for DecompressLayer {
+    type Service = Decompress<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Decompress {
+            inner,
+            max_decoded_size: self.max_decoded_size,
+        }
+    }
+}
+
+// ===== impl DecompressFuture =====
+
+impl<F, B> Future for DecompressFuture<F>
+where
+    F: Future<Item = Response<B>>,
+    B: HttpBody,
+{
+    type Item = Response<DecompressBody<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut response = try_ready!(self.inner.poll());
+
+        let status = response.status();
+        let decoder = if self.has_body
+            && status != StatusCode::NO_CONTENT
+            && status != StatusCode::NOT_MODIFIED
+        {
+            Decoder::for_headers(response.headers(), self.max_decoded_size)
+        } else {
+            None
+        };
+
+        if decoder.is_some() {
+            let headers = response.headers_mut();
+            headers.remove(CONTENT_ENCODING);
+            headers.remove(CONTENT_LENGTH);
+        }
+
+        let response = response.map(|inner| DecompressBody { inner, decoder });
+        Ok(Async::Ready(response))
+    }
+}
+
+// ===== impl DecompressBody =====
+
+impl<B> HttpBody for DecompressBody<B>
+where
+    B: HttpBody,
+    B::Data: Into<Chunk>,
+    B::Error: Into<crate::Error>,
+{
+    type Data = Chunk;
+    type Error = crate::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        loop {
+            let data = match self.inner.poll_data() {
+                Ok(Async::Ready(data)) => data,
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(e) => return Err(e.into()),
+            };
+
+            let decoder = match &mut self.decoder {
+                Some(decoder) => decoder,
+                None => return Ok(Async::Ready(data.map(Into::into))),
+            };
+
+            let decoded = match data {
+                Some(data) => {
+                    let chunk: Chunk = data.into();
+                    decoder.decode(&chunk).map_err(into_error)?
+                }
+                None => {
+                    // The body is done, flush what is left in the decoder.
+                    let decoder = self.decoder.take().expect("decoder");
+                    let decoded = decoder.finish().map_err(into_error)?;
+                    if decoded.is_empty() {
+                        return Ok(Async::Ready(None));
+                    }
+                    decoded
+                }
+            };
+
+            // Input that only fills the decoder's state yields nothing, so
+            // keep reading.
+            if !decoded.is_empty() {
+                return Ok(Async::Ready(Some(Chunk::from(decoded))));
+            }
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        self.inner.poll_trailers().map_err(Into::into)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.decoder.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self.decoder {
+            // The decoded length is unknown.
+            Some(_) => SizeHint::default(),
+            None => self.inner.size_hint(),
+        }
+    }
+}
+
+// ===== impl DecodedLengthError =====
+
+impl DecodedLengthError {
+    /// The maximum number of bytes a decoded body could contain.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+impl fmt::Display for DecodedLengthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "decoded response body exceeded the limit of {} bytes",
+            self.limit
+        )
+    }
+}
+
+impl std::error::Error for DecodedLengthError {}
+
+/// Convert a decoding error, unwrapping a `DecodedLengthError` raised by
+/// `Output` so it can be downcast.
+fn into_error(error: io::Error) -> crate::Error {
+    let is_length = error
+        .get_ref()
+        .is_some_and(|inner| inner.is::<DecodedLengthError>());
+
+    if is_length {
+        error.into_inner().unwrap()
+    } else {
+        Box::new(error)
+    }
+}
+
+// ===== impl Decoder =====
+
+impl Decoder {
+    /// Create the decoder for the `Content-Encoding` in `headers`, if it is
+    /// a single supported encoding.
+    fn for_headers(headers: &HeaderMap, max_decoded_size: Option<usize>) -> Option<Self> {
+        let encoding = headers.get(CONTENT_ENCODING)?.to_str().ok()?.trim();
+        let output = Output::new(max_decoded_size);
+
+        if encoding.eq_ignore_ascii_case("gzip") || encoding.eq_ignore_ascii_case("x-gzip") {
+            Some(Decoder::Gzip(GzDecoder::new(output)))
+        } else if encoding.eq_ignore_ascii_case("deflate") {
+            Some(Decoder::Deflate(Vec::new(), output))
+        } else if encoding.eq_ignore_ascii_case("br") {
+            let decoder = brotli::DecompressorWriter::new(output, BROTLI_BUFFER_SIZE);
+            Some(Decoder::Brotli(Box::new(decoder)))
+        } else {
+            None
+        }
+    }
+
+    /// Decode `input`, returning the output that is ready.
+    fn decode(&mut self, input: &[u8]) -> io::Result<Vec<u8>> {
+        if let Decoder::Deflate(prefix, _) = self {
+            prefix.extend_from_slice(input);
+            if prefix.len() < 2 {
+                return Ok(Vec::new());
+            }
+
+            let prefix = self.detect_deflate();
+            return self.write(&prefix);
+        }
+
+        self.write(input)
+    }
+
+    /// Finish decoding, returning the remaining output.
+    ///
+    /// Fails if the encoded stream was truncated.
+    fn finish(mut self) -> io::Result<Vec<u8>> {
+        let mut decoded = match self {
+            Decoder::Deflate(..) => {
+                let prefix = self.detect_deflate();
+                self.write(&prefix)?
+            }
+            _ => Vec::new(),
+        };
+
+        let output = match self {
+            Decoder::Gzip(decoder) => decoder.finish()?,
+            Decoder::Deflate(_, output) => output,
+            Decoder::Zlib(decoder) => decoder.finish()?,
+            Decoder::RawDeflate(decoder) => decoder.finish()?,
+            Decoder::Brotli(decoder) => decoder.into_inner().map_err(|_| {
+                io::Error::new(io::ErrorKind::UnexpectedEof, "truncated brotli stream")
+            })?,
+        };
+
+        decoded.extend_from_slice(&output.buf);
+        Ok(decoded)
+    }
+
+    /// Replace a `Deflate` decoder with one for the detected format,
+    /// returning the bytes read so far.
+    ///
+    /// A zlib stream starts with a header whose compression method is
+    /// deflate, and whose first two bytes are a multiple of 31.
+    fn detect_deflate(&mut self) -> Vec<u8> {
+        let placeholder = Decoder::Deflate(Vec::new(), Output::new(None));
+        let (prefix, output) = match mem::replace(self, placeholder) {
+            Decoder::Deflate(prefix, output) => (prefix, output),
+            decoder => {
+                *self = decoder;
+                return Vec::new();
+            }
+        };
+
+        let zlib = match prefix.get(..2) {
+            Some(&[cmf, flg]) => {
+                cmf & 0x0f == 8 && (u16::from(cmf) << 8 | u16::from(flg)) % 31 == 0
+            }
+            _ => false,
+        };
+
+        *self = if zlib {
+            Decoder::Zlib(ZlibDecoder::new(output))
+        } else {
+            Decoder::RawDeflate(DeflateDecoder::new(output))
+        };
+        prefix
+    }
+
+    /// Write `input` to the decoder, returning the output that is ready.
+    fn write(&mut self, input: &[u8]) -> io::Result<Vec<u8>> {
+        let writer: &mut dyn Write = match self {
+            Decoder::Gzip(decoder) => decoder,
+            Decoder::Deflate(..) => unreachable!("deflate format not detected"),
+            Decoder::Zlib(decoder) => decoder,
+            Decoder::RawDeflate(decoder) => decoder,
+            Decoder::Brotli(decoder) => &mut **decoder,
+        };
+        writer.write_all(input)?;
+        writer.flush()?;
+        Ok(mem::take(&mut self.output().buf))
+    }
+
+    fn output(&mut self) -> &mut Output {
+        match self {
+            Decoder::Gzip(decoder) => decoder.get_mut(),
+            Decoder::Deflate(_, output) => output,
+            Decoder::Zlib(decoder) => decoder.get_mut(),
+            Decoder::RawDeflate(decoder) => decoder.get_mut(),
+            Decoder::Brotli(decoder) => decoder.get_mut(),
+        }
+    }
+}
+
+impl fmt::Debug for Decoder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Decoder::Gzip(_) => f.write_str("Gzip"),
+            Decoder::Deflate(..) => f.write_str("Deflate"),
+            Decoder::Zlib(_) => f.write_str("Zlib"),
+            Decoder::RawDeflate(_) => f.write_str("RawDeflate"),
+            Decoder::Brotli(_) => f.write_str("Brotli"),
+        }
+    }
+}
+
+// ===== impl Output =====
+
+impl Output {
+    fn new(limit: Option<usize>) -> Self {
+        Output {
+            buf: Vec::new(),
+            remaining: limit,
+            limit: limit.unwrap_or(0),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(remaining) = &mut self.remaining {
+            if buf.len() > *remaining {
+                let limit = self.limit;
+                return Err(io::Error::other(DecodedLengthError { limit }));
+            }
+            *remaining -= buf.len();
+        }
+
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+use super::{Change, Connect, ConnectExecutor, ConnectFuture, Connection};
+use futures::{Async, Future, Poll, Stream};
+use http_body::Body as HttpBody;
+use log::debug;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+use tower_discover::Discover;
+use tower_http_util::connection::HttpMakeConnection;
+use tower_service::Service;
+
+/// Discovers `Connection`s to the endpoints of a `Stream` of [`Change`]s.
+///
+/// This adapts any source of endpoint targets, such as a file watcher, DNS
+/// polling or a fixed list, into a `Discover` for balancers and other
+/// middleware that work with a changing set of services.
+///
+/// Each inserted target is connected to with a `Connect`, and yielded once
+/// the connection is established. Targets that fail to connect are logged
+/// and skipped until they are inserted again. Removing a target that is
+/// still connecting cancels the connection attempt.
+///
+/// [`Change`]: ./enum.Change.html
+pub struct ConnectDiscover<St, A, B, C, E>
+where
+    B: HttpBody,
+    C: HttpMakeConnection<A>,
+{
+    connect: Connect<A, B, C, E>,
+    changes: Option<St>,
+    /// Targets waiting for `Connect` to be ready.
+    queued: VecDeque<A>,
+    connecting: Vec<(A, ConnectFuture<A, B, C, E>)>,
+    /// Targets yielded as inserted, and not removed since.
+    inserted: HashSet<A>,
+    removed: VecDeque<A>,
+}
+
+// ===== impl ConnectDiscover =====
+
+impl<St, A, B, C, E> ConnectDiscover<St, A, B, C, E>
+where
+    A: Hash + Eq,
+    B: HttpBody,
+    C: HttpMakeConnection<A>,
+{
+    /// Discover connections to the targets inserted by `changes`.
+    pub fn new(connect: Connect<A, B, C, E>, changes: St) -> Self {
+        ConnectDiscover {
+            connect,
+            changes: Some(changes),
+            queued: VecDeque::new(),
+            connecting: Vec::new(),
+            inserted: HashSet::new(),
+            removed: VecDeque::new(),
+        }
+    }
+
+    /// The number of targets that are queued or connecting.
+    pub(super) fn connecting(&self) -> usize {
+        self.queued.len() + self.connecting.len()
+    }
+
+    /// Returns `true` once the changes ended, and no target is left to
+    /// connect to.
+    pub(super) fn is_exhausted(&self) -> bool {
+        self.changes.is_none() && self.queued.is_empty() && self.connecting.is_empty()
+    }
+
+    /// Connect to an inserted target again, without yielding its removal.
+    pub(super) fn reconnect(&mut self, target: A) {
+        if self.inserted.remove(&target) {
+            self.queued.push_back(target);
+        }
+    }
+
+    /// Forget an inserted target whose connection failed, so that it is
+    /// connected to again if it is inserted again.
+    pub(super) fn forget(&mut self, target: &A) {
+        self.inserted.remove(target);
+    }
+}
+
+impl<St, A, B, C, E> ConnectDiscover<St, A, B, C, E>
+where
+    St: Stream<Item = Change<A>>,
+    St::Error: Into<crate::Error>,
+    A: Clone + Hash + Eq,
+    C: HttpMakeConnection<A> + 'static,
+    C::Error: fmt::Display,
+    C::Connection: Send + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<crate::Error>,
+    E: ConnectExecutor<C::Connection, B> + Clone,
+{
+    /// Apply the changes that are ready.
+    fn poll_changes(&mut self) -> Result<(), crate::Error> {
+        while let Some(changes) = &mut self.changes {
+            let change = match changes.poll() {
+                Ok(Async::Ready(Some(change))) => change,
+                Ok(Async::Ready(None)) => {
+                    self.changes = None;
+                    break;
+                }
+                Ok(Async::NotReady) => break,
+                Err(e) => return Err(e.into()),
+            };
+
+            match change {
+                Change::Insert(target) => {
+                    let known = self.inserted.contains(&target)
+                        || self.queued.contains(&target)
+                        || self.connecting.iter().any(|(t, _)| *t == target);
+                    if !known {
+                        self.queued.push_back(target);
+                    }
+                }
+                Change::Remove(target) => {
+                    self.queued.retain(|t| *t != target);
+                    self.connecting.retain(|(t, _)| *t != target);
+                    if self.inserted.remove(&target) {
+                        self.removed.push_back(target);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Start connecting to queued targets while `Connect` is ready.
+    fn start_connects(&mut self) {
+        while !self.queued.is_empty() {
+            match self.connect.poll_ready() {
+                Ok(Async::Ready(())) => {}
+                Ok(Async::NotReady) => return,
+                Err(e) => {
+                    // The target is dropped, like any other failed connect.
+                    debug!("endpoint connect error: {}", e);
+                    self.queued.pop_front();
+                    continue;
+                }
+            }
+
+            let target = self.queued.pop_front().expect("queue is not empty");
+            let fut = self.connect.call(target.clone());
+            self.connecting.push((target, fut));
+        }
+    }
+
+    /// Poll the connection attempts, returning the first one established.
+    fn poll_connecting(&mut self) -> Option<(A, Connection<B>)> {
+        let mut i = 0;
+        while i < self.connecting.len() {
+            match self.connecting[i].1.poll() {
+                Ok(Async::NotReady) => i += 1,
+                Ok(Async::Ready(connection)) => {
+                    let (target, _) = self.connecting.swap_remove(i);
+                    return Some((target, connection));
+                }
+                Err(e) => {
+                    debug!("endpoint connect error: {}", e);
+                    self.connecting.swap_remove(i);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl<St, A, B, C, E> Discover for ConnectDiscover<St, A, B, C, E>
+where
+    St: Stream<Item = Change<A>>,
+    St::Error: Into<crate::Error>,
+    A: Clone + Hash + Eq,
+    C: HttpMakeConnection<A> + 'static,
+    C::Error: fmt::Display,
+    C::Connection: Send + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<crate::Error>,
+    E: ConnectExecutor<C::Connection, B> + Clone,
+{
+    type Key = A;
+    type Service = Connection<B>;
+    type Error = crate::Error;
+
+    /// Never completes after the `Stream` of changes ended, the set of
+    /// endpoints just stays the same.
+    fn poll(&mut self) -> Poll<tower_discover::Change<A, Connection<B>>, Self::Error> {
+        self.poll_changes()?;
+
+        if let Some(target) = self.removed.pop_front() {
+            event!("endpoint removed");
+            return Ok(Async::Ready(tower_discover::Change::Remove(target)));
+        }
+
+        self.start_connects();
+
+        match self.poll_connecting() {
+            Some((target, connection)) => {
+                event!("endpoint inserted");
+                self.inserted.insert(target.clone());
+                Ok(Async::Ready(tower_discover::Change::Insert(
+                    target, connection,
+                )))
+            }
+            None => Ok(Async::NotReady),
+        }
+    }
+}
+
+impl<St, A, B, C, E> fmt::Debug for ConnectDiscover<St, A, B, C, E>
+where
+    B: HttpBody,
+    C: HttpMakeConnection<A>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConnectDiscover")
+            .field("queued", &self.queued.len())
+            .field("connecting", &self.connecting.len())
+            .field("inserted", &self.inserted.len())
+            .finish()
+    }
+}
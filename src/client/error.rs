@@ -0,0 +1,143 @@
+use super::ResponseHeadTimedOut;
+use std::fmt;
+use std::io;
+
+/// Errors produced by `Connection` and `Client`.
+///
+/// Each variant classifies why a request failed, and keeps the underlying
+/// error as its `source`. Its `Display` only describes the kind of failure,
+/// walk the sources for the details.
+#[derive(Debug)]
+pub enum Error {
+    /// The connection was closed before the request completed.
+    ConnectionClosed(crate::Error),
+    /// The request was canceled before it was sent.
+    Canceled(crate::Error),
+    /// The connection timed out, see `Connect::response_head_timeout`.
+    Timeout(crate::Error),
+    /// The peer violated the HTTP protocol, or the connection failed.
+    Protocol(crate::Error),
+    /// The request body could not be written.
+    BodyWrite(crate::Error),
+    /// The connection reached a limit set on `Connect` and was retired.
+    ///
+    /// A retired connection accepts no more requests, and should be
+    /// replaced by a new one.
+    Retired(crate::Error),
+    /// The background task of the connection failed, with the error that
+    /// ended it as the source.
+    ///
+    /// This is returned once the connection is polled after the failure,
+    /// rather than by the requests in flight when it happened. The
+    /// connection accepts no more requests.
+    Background(crate::Error),
+}
+
+/// The source of `Error::Retired`.
+#[derive(Debug)]
+struct RetiredError;
+
+// ===== impl Error =====
+
+impl Error {
+    /// Classify the error that ended the background task of a connection.
+    pub(super) fn background(error: crate::Error) -> Self {
+        if error.is::<ResponseHeadTimedOut>() {
+            Error::Timeout(error)
+        } else {
+            Error::Background(error)
+        }
+    }
+
+    pub(super) fn retired() -> Self {
+        Error::Retired(Box::new(RetiredError))
+    }
+
+    /// Returns the underlying error.
+    pub fn into_source(self) -> crate::Error {
+        match self {
+            Error::ConnectionClosed(e)
+            | Error::Canceled(e)
+            | Error::Timeout(e)
+            | Error::Protocol(e)
+            | Error::BodyWrite(e)
+            | Error::Retired(e)
+            | Error::Background(e) => e,
+        }
+    }
+
+    fn inner(&self) -> &crate::Error {
+        match self {
+            Error::ConnectionClosed(e)
+            | Error::Canceled(e)
+            | Error::Timeout(e)
+            | Error::Protocol(e)
+            | Error::BodyWrite(e)
+            | Error::Retired(e)
+            | Error::Background(e) => e,
+        }
+    }
+}
+
+impl From<hyper::Error> for Error {
+    fn from(error: hyper::Error) -> Self {
+        if error.is_canceled() {
+            Error::Canceled(error.into())
+        } else if is_timeout(&error) {
+            Error::Timeout(error.into())
+        } else if error.is_closed() || error.is_incomplete_message() {
+            Error::ConnectionClosed(error.into())
+        } else if error.is_body_write_aborted() || is_body_error(&error) {
+            Error::BodyWrite(error.into())
+        } else {
+            Error::Protocol(error.into())
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self {
+            Error::ConnectionClosed(_) => "connection closed",
+            Error::Canceled(_) => "request canceled",
+            Error::Timeout(_) => "connection timed out",
+            Error::Protocol(_) => "protocol error",
+            Error::BodyWrite(_) => "error writing request body",
+            Error::Retired(_) => "connection retired",
+            Error::Background(_) => "connection background task failed",
+        };
+        f.write_str(kind)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&**self.inner())
+    }
+}
+
+// ===== impl RetiredError =====
+
+impl fmt::Display for RetiredError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("connection reached its maximum lifetime or number of requests")
+    }
+}
+
+impl std::error::Error for RetiredError {}
+
+/// Returns `true` if `error` was caused by the connection timing out, see
+/// `Connect::response_head_timeout`.
+fn is_timeout(error: &hyper::Error) -> bool {
+    std::error::Error::source(error)
+        .and_then(|source| source.downcast_ref::<io::Error>())
+        .is_some_and(|e| e.kind() == io::ErrorKind::TimedOut)
+}
+
+/// Returns `true` if `error` came from the request body.
+///
+/// The only user errors with a cause on the client are errors from the
+/// request body.
+fn is_body_error(error: &hyper::Error) -> bool {
+    error.is_user() && std::error::Error::source(error).is_some()
+}
@@ -1,11 +1,31 @@
+use super::response_head::InFlight;
+use super::Error;
+use crate::trace::Span;
 use crate::Body;
 use futures::{Async, Future, Poll};
-use hyper::Response;
+use hyper::{Request, Response};
 
 /// Lift a hyper ResponseFuture to one which returns a `tower_http::Body`.
 #[derive(Debug)]
 pub struct ResponseFuture<F> {
     pub(super) inner: F,
+    pub(super) span: Span,
+    /// Keeps the response head timer of the connection running until the
+    /// response head arrives.
+    pub(super) in_flight: Option<InFlight>,
+}
+
+/// Create the span that a `ResponseFuture` for `req` is polled within.
+pub(super) fn request_span<B>(req: &Request<B>) -> Span {
+    let span = span!(
+        "request",
+        method = %req.method(),
+        uri = %req.uri(),
+        version = ?req.version()
+    );
+    // Only referenced by the span when `tracing` is enabled.
+    let _ = req;
+    span
 }
 
 impl<F> Future for ResponseFuture<F>
@@ -13,16 +33,24 @@ where
     F: Future<Item = Response<hyper::Body>, Error = hyper::Error>,
 {
     type Item = Response<Body>;
-    type Error = hyper::Error;
+    type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let _enter = self.span.enter();
+
         match self.inner.poll() {
             Ok(futures::Async::Ready(body)) => {
+                self.in_flight = None;
+                event!(status = body.status().as_u16(), "response");
                 let body = body.map(Body::from);
                 Ok(Async::Ready(body))
             }
             Ok(Async::NotReady) => Ok(Async::NotReady),
-            Err(e) => Err(e),
+            Err(e) => {
+                self.in_flight = None;
+                event!(error = %e, "request error");
+                Err(e.into())
+            }
         }
     }
 }
@@ -26,13 +26,33 @@
 //! [`hyper::Client`]: ../../hyper/struct.Client.html
 
 mod background;
+mod balance;
 mod connect;
 mod connection;
+#[cfg(feature = "compression")]
+mod decompress;
+mod discover;
+mod error;
 mod future;
+mod redirect;
+mod response_head;
+mod retry;
+mod router;
 
+pub use self::balance::{Balance, BalanceFuture, Change, NoEndpoints};
 pub use self::connect::{Connect, ConnectError, ConnectExecutor, ConnectFuture};
-pub use self::connection::Connection;
+pub use self::connection::{Close, Connection};
+#[cfg(feature = "compression")]
+pub use self::decompress::{
+    DecodedLengthError, Decompress, DecompressBody, DecompressFuture, DecompressLayer,
+};
+pub use self::discover::ConnectDiscover;
+pub use self::error::Error;
 pub use self::future::ResponseFuture;
+pub use self::redirect::{FinalUri, Redirect, RedirectFuture, RedirectLayer, TooManyRedirects};
+pub use self::response_head::ResponseHeadTimedOut;
+pub use self::retry::{RetryPolicy, RetryableError};
+pub use self::router::{Router, RouterFuture};
 pub use hyper::client::conn::Builder;
 
 use crate::body::{Body, LiftBody};
@@ -125,7 +145,7 @@ where
     B::Error: Into<crate::Error>,
 {
     type Response = Response<Body>;
-    type Error = hyper::Error;
+    type Error = Error;
     type Future = ResponseFuture<client::ResponseFuture>;
 
     /// Poll to see if the service is ready, since `hyper::Client`
@@ -136,7 +156,15 @@ where
 
     /// Send the sepcficied request to the inner `hyper::Client`
     fn call(&mut self, req: Request<B>) -> Self::Future {
-        let inner = self.inner.request(req.map(LiftBody::from));
-        ResponseFuture { inner }
+        let span = future::request_span(&req);
+        let inner = {
+            let _enter = span.enter();
+            self.inner.request(req.map(LiftBody::from))
+        };
+        ResponseFuture {
+            inner,
+            span,
+            in_flight: None,
+        }
     }
 }
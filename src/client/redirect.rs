@@ -0,0 +1,489 @@
+use crate::body::Replayable;
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{
+    HeaderMap, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, HOST,
+    LOCATION, PROXY_AUTHORIZATION, TRANSFER_ENCODING,
+};
+use http::uri::{Authority, Scheme};
+use http::{Method, Request, Response, StatusCode, Uri, Version};
+use http_body::Body as HttpBody;
+use std::fmt;
+use std::mem;
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// The number of redirects followed by default.
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// Follows redirect responses of the inner service.
+///
+/// `301`, `302`, `303`, `307` and `308` responses with a `Location` header
+/// are followed by sending a new request to that location:
+///
+/// - `303 See Other` is followed with a `GET` request without a body, unless
+///   the original request was a `HEAD` request.
+/// - `301 Moved Permanently` and `302 Found` are followed with a `GET`
+///   request without a body if the original request was a `POST`, like
+///   browsers do. Other methods are kept.
+/// - `307 Temporary Redirect` and `308 Permanent Redirect` are followed with
+///   the same method and body.
+///
+/// Request bodies are [`Replayable`] so they can be sent again. Redirects
+/// that would resend a body that outgrew its buffer are not followed, and
+/// the redirect response is returned instead.
+///
+/// When a redirect leads to a different origin, the `Authorization`,
+/// `Proxy-Authorization`, `Cookie` and `Host` headers are removed.
+///
+/// Every response carries a [`FinalUri`] extension with the URI of the
+/// request that produced it.
+///
+/// Redirects to another origin are only sent there if the inner service
+/// routes requests by their URI, such as a [`Router`]. A `Connection` sends
+/// every request to the peer it is connected to, so wrap it only to follow
+/// redirects within its origin.
+///
+/// [`Replayable`]: ../body/struct.Replayable.html
+/// [`FinalUri`]: ./struct.FinalUri.html
+/// [`Router`]: ./struct.Router.html
+#[derive(Clone, Debug)]
+pub struct Redirect<S> {
+    inner: S,
+    max_redirects: usize,
+}
+
+/// A `Layer` that wraps services in `Redirect`.
+#[derive(Clone, Debug)]
+pub struct RedirectLayer {
+    max_redirects: usize,
+}
+
+/// The future returned by `Redirect`.
+pub struct RedirectFuture<S, B>
+where
+    S: Service<Request<Replayable<B>>>,
+{
+    service: S,
+    state: State<S::Future, B>,
+    /// The request being sent, without its body.
+    head: Head,
+    /// A copy of the request body to send on redirects, if it can be
+    /// replayed.
+    body: Option<Replayable<B>>,
+    remaining: usize,
+    max_redirects: usize,
+}
+
+/// Error produced when a request is redirected more often than allowed.
+#[derive(Debug)]
+pub struct TooManyRedirects {
+    max: usize,
+}
+
+/// The URI of the request that produced a response, after redirects.
+///
+/// `Redirect` inserts this into the extensions of every response.
+#[derive(Clone, Debug)]
+pub struct FinalUri {
+    uri: Uri,
+}
+
+enum State<F, B> {
+    /// Waiting for the service to be ready to send a redirected request.
+    ///
+    /// Boxed, as a request is much larger than most response futures.
+    NotReady(Option<Box<Request<Replayable<B>>>>),
+    /// Waiting for the response.
+    Called(F),
+}
+
+#[derive(Debug)]
+struct Head {
+    method: Method,
+    uri: Uri,
+    version: Version,
+    headers: HeaderMap,
+}
+
+// ===== impl Redirect =====
+
+impl<S> Redirect<S> {
+    /// Wrap `inner`, following up to 10 redirects per request.
+    pub fn new(inner: S) -> Self {
+        Redirect {
+            inner,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+        }
+    }
+
+    /// Set the maximum number of redirects followed per request.
+    ///
+    /// Requests redirected more often fail with [`TooManyRedirects`]. A
+    /// maximum of zero disables redirects, and returns redirect responses
+    /// as they are.
+    ///
+    /// [`TooManyRedirects`]: ./struct.TooManyRedirects.html
+    pub fn max_redirects(mut self, max: usize) -> Self {
+        self.max_redirects = max;
+        self
+    }
+}
+
+impl<S, B, RespBody> Service<Request<Replayable<B>>> for Redirect<S>
+where
+    S: Service<Request<Replayable<B>>, Response = Response<RespBody>> + Clone,
+    S::Error: Into<crate::Error>,
+    B: HttpBody + Default,
+{
+    type Response = Response<RespBody>;
+    type Error = crate::Error;
+    type Future = RedirectFuture<S, B>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready().map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Replayable<B>>) -> Self::Future {
+        let head = Head {
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            version: req.version(),
+            headers: req.headers().clone(),
+        };
+        let body = req.body().replay();
+
+        // Keep the service that was polled ready, and hand the future a
+        // clone to send redirected requests with.
+        let clone = self.inner.clone();
+        let mut service = mem::replace(&mut self.inner, clone);
+        let fut = service.call(req);
+
+        RedirectFuture {
+            service,
+            state: State::Called(fut),
+            head,
+            body,
+            remaining: self.max_redirects,
+            max_redirects: self.max_redirects,
+        }
+    }
+}
+
+// ===== impl RedirectLayer =====
+
+impl RedirectLayer {
+    /// Create a layer following up to 10 redirects per request.
+    pub fn new() -> Self {
+        RedirectLayer {
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+        }
+    }
+
+    /// Set the maximum number of redirects followed per request.
+    ///
+    /// See [`Redirect::max_redirects`].
+    ///
+    /// [`Redirect::max_redirects`]: ./struct.Redirect.html#method.max_redirects
+    pub fn max_redirects(mut self, max: usize) -> Self {
+        self.max_redirects = max;
+        self
+    }
+}
+
+impl Default for RedirectLayer {
+    fn default() -> Self {
+        RedirectLayer::new()
+    }
+}
+
+impl<S> Layer<S> for RedirectLayer {
+    type Service = Redirect<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Redirect::new(inner).max_redirects(self.max_redirects)
+    }
+}
+
+// ===== impl RedirectFuture =====
+
+impl<S, B, RespBody> RedirectFuture<S, B>
+where
+    S: Service<Request<Replayable<B>>, Response = Response<RespBody>>,
+    B: HttpBody + Default,
+{
+    /// Build the request that follows `response`, if it is a redirect that
+    /// should be followed.
+    fn follow(&mut self, response: &Response<RespBody>) -> Option<Request<Replayable<B>>> {
+        if self.max_redirects == 0 {
+            return None;
+        }
+
+        let status = response.status();
+        let keep_body = match status {
+            StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => self.head.method != Method::POST,
+            StatusCode::SEE_OTHER => self.head.method == Method::HEAD,
+            StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => true,
+            _ => return None,
+        };
+
+        let location = response.headers().get(LOCATION)?.to_str().ok()?;
+        let uri = resolve(&self.head.uri, location)?;
+
+        let body = if keep_body {
+            self.body.as_ref()?.replay()?
+        } else {
+            if self.head.method != Method::HEAD {
+                self.head.method = Method::GET;
+            }
+            for header in &[
+                CONTENT_ENCODING,
+                CONTENT_LENGTH,
+                CONTENT_TYPE,
+                TRANSFER_ENCODING,
+            ] {
+                self.head.headers.remove(header);
+            }
+            let empty = Replayable::new(B::default(), 0);
+            self.body = empty.replay();
+            empty
+        };
+
+        if !same_origin(&self.head.uri, &self.head.headers, &uri) {
+            for header in &[AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE, HOST] {
+                self.head.headers.remove(header);
+            }
+        }
+
+        event!(status = status.as_u16(), location = %uri, "following redirect");
+        self.head.uri = uri;
+
+        let mut request = Request::new(body);
+        *request.method_mut() = self.head.method.clone();
+        *request.uri_mut() = self.head.uri.clone();
+        *request.version_mut() = self.head.version;
+        *request.headers_mut() = self.head.headers.clone();
+        Some(request)
+    }
+}
+
+impl<S, B, RespBody> Future for RedirectFuture<S, B>
+where
+    S: Service<Request<Replayable<B>>, Response = Response<RespBody>>,
+    S::Error: Into<crate::Error>,
+    B: HttpBody + Default,
+{
+    type Item = Response<RespBody>;
+    type Error = crate::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let fut = match self.state {
+                State::NotReady(ref mut request) => {
+                    try_ready!(self.service.poll_ready().map_err(Into::into));
+                    let request = request.take().expect("polled after complete");
+                    self.service.call(*request)
+                }
+                State::Called(ref mut fut) => {
+                    let mut response = try_ready!(fut.poll().map_err(Into::into));
+
+                    let request = match self.follow(&response) {
+                        Some(request) => request,
+                        None => {
+                            let uri = self.head.uri.clone();
+                            response.extensions_mut().insert(FinalUri { uri });
+                            return Ok(Async::Ready(response));
+                        }
+                    };
+
+                    if self.remaining == 0 {
+                        let max = self.max_redirects;
+                        return Err(Box::new(TooManyRedirects { max }));
+                    }
+                    self.remaining -= 1;
+
+                    self.state = State::NotReady(Some(Box::new(request)));
+                    continue;
+                }
+            };
+
+            self.state = State::Called(fut);
+        }
+    }
+}
+
+impl<S, B> fmt::Debug for RedirectFuture<S, B>
+where
+    S: Service<Request<Replayable<B>>> + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RedirectFuture")
+            .field("service", &self.service)
+            .field("head", &self.head)
+            .field("remaining", &self.remaining)
+            .finish()
+    }
+}
+
+// ===== impl TooManyRedirects =====
+
+impl TooManyRedirects {
+    /// The maximum number of redirects that could be followed.
+    pub fn max(&self) -> usize {
+        self.max
+    }
+}
+
+impl fmt::Display for TooManyRedirects {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "request exceeded the limit of {} redirects", self.max)
+    }
+}
+
+impl std::error::Error for TooManyRedirects {}
+
+// ===== impl FinalUri =====
+
+impl FinalUri {
+    /// The URI of the last request sent.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+}
+
+/// Resolve a `Location` header value relative to the URI of the request,
+/// as described in [RFC 3986, section 5.2].
+///
+/// Fragments are dropped, as a `Uri` can not hold them.
+///
+/// [RFC 3986, section 5.2]: https://tools.ietf.org/html/rfc3986#section-5.2
+fn resolve(base: &Uri, location: &str) -> Option<Uri> {
+    let location = location.split('#').next().unwrap_or("");
+
+    // Absolute URIs replace the base entirely.
+    if has_scheme(location) {
+        let uri = location.parse::<Uri>().ok()?;
+        let path = remove_dot_segments(uri.path());
+        return join(uri.scheme_part(), uri.authority_part(), &path, uri.query());
+    }
+
+    let (rest, query) = match location.find('?') {
+        Some(i) => (&location[..i], Some(&location[i + 1..])),
+        None => (location, None),
+    };
+
+    // Network path references keep only the scheme of the base.
+    if let Some(network) = rest.strip_prefix("//") {
+        let end = network.find('/').unwrap_or(network.len());
+        let authority = network[..end].parse::<Authority>().ok()?;
+        let path = remove_dot_segments(&network[end..]);
+        let scheme = base.scheme_part().cloned().unwrap_or(Scheme::HTTP);
+        return join(Some(&scheme), Some(&authority), &path, query);
+    }
+
+    let (path, query) = if rest.is_empty() {
+        (base.path().to_string(), query.or_else(|| base.query()))
+    } else if rest.starts_with('/') {
+        (remove_dot_segments(rest), query)
+    } else {
+        (remove_dot_segments(&merge(base, rest)), query)
+    };
+
+    join(base.scheme_part(), base.authority_part(), &path, query)
+}
+
+/// Returns `true` if `location` starts with a URI scheme.
+fn has_scheme(location: &str) -> bool {
+    let scheme = match location.find(':') {
+        Some(i) => &location[..i],
+        None => return false,
+    };
+
+    let mut chars = scheme.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+}
+
+/// Merge a relative path with the path of `base`.
+fn merge(base: &Uri, path: &str) -> String {
+    let base_path = base.path();
+    let dir = &base_path[..base_path.rfind('/').map(|i| i + 1).unwrap_or(0)];
+    if dir.is_empty() {
+        format!("/{}", path)
+    } else {
+        format!("{}{}", dir, path)
+    }
+}
+
+/// Remove the `.` and `..` segments of `path`.
+fn remove_dot_segments(path: &str) -> String {
+    let mut output: Vec<&str> = Vec::new();
+    let segments: Vec<&str> = path.split('/').collect();
+
+    for (i, segment) in segments.iter().enumerate() {
+        let last = i == segments.len() - 1;
+        match *segment {
+            "." | ".." => {
+                if *segment == ".." && output.len() > 1 {
+                    output.pop();
+                }
+                // A trailing dot segment still refers to a directory.
+                if last {
+                    output.push("");
+                }
+            }
+            segment => output.push(segment),
+        }
+    }
+
+    let path = output.join("/");
+    if path.starts_with('/') {
+        path
+    } else {
+        format!("/{}", path)
+    }
+}
+
+/// Build a URI from its resolved parts.
+fn join(
+    scheme: Option<&Scheme>,
+    authority: Option<&Authority>,
+    path: &str,
+    query: Option<&str>,
+) -> Option<Uri> {
+    let path_and_query = match query {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_string(),
+    };
+
+    let mut builder = Uri::builder();
+    if let (Some(scheme), Some(authority)) = (scheme, authority) {
+        builder.scheme(scheme.clone()).authority(authority.clone());
+    }
+    builder.path_and_query(path_and_query.as_str()).build().ok()
+}
+
+/// Returns `true` if `next` has the same origin as the request `uri` with
+/// `headers`.
+///
+/// Requests in origin form have no scheme or authority, so their `Host`
+/// header stands in for the authority, and schemes are only compared if
+/// both are known.
+fn same_origin(uri: &Uri, headers: &HeaderMap, next: &Uri) -> bool {
+    let authority = uri.authority_part().cloned().or_else(|| {
+        headers
+            .get(HOST)
+            .and_then(|host| host.to_str().ok())
+            .and_then(|host| host.parse::<Authority>().ok())
+    });
+    let next_authority = next.authority_part().cloned().or_else(|| authority.clone());
+
+    let same_scheme = match (uri.scheme_part(), next.scheme_part()) {
+        (Some(a), Some(b)) => a == b,
+        _ => true,
+    };
+
+    same_scheme && authority == next_authority
+}
@@ -0,0 +1,233 @@
+use futures::{Async, Future, Poll};
+use log::debug;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_timer::{clock, Delay};
+
+/// Error produced when a request waited for its response head while nothing
+/// was read from the peer for longer than the response head timeout.
+///
+/// Once a connection failed with this error it should be discarded, the
+/// peer is assumed to be unreachable.
+#[derive(Debug)]
+pub struct ResponseHeadTimedOut {
+    timeout: Duration,
+}
+
+/// Tracks reads and in-flight requests for a single connection.
+#[derive(Clone, Debug)]
+pub(super) struct Liveness {
+    activity: Arc<Mutex<Activity>>,
+}
+
+/// Marks a request as in flight until dropped.
+#[derive(Debug)]
+pub(super) struct InFlight {
+    liveness: Liveness,
+}
+
+/// An IO that reports reads to a `Liveness`, and fails once it expired.
+#[derive(Debug)]
+pub(super) struct LivenessIo<I> {
+    inner: I,
+    liveness: Liveness,
+}
+
+/// Fails a connection once its peer has been quiet for `timeout` while a
+/// request was in flight.
+#[derive(Debug)]
+pub(super) struct HeadTimeout {
+    liveness: Liveness,
+    timeout: Duration,
+    delay: Option<Delay>,
+}
+
+#[derive(Debug)]
+struct Activity {
+    in_flight: usize,
+    /// When bytes were last read, or the first request in flight started,
+    /// whichever is later.
+    last_active: Instant,
+    /// Set once the response head timed out, after which reads fail.
+    expired: bool,
+}
+
+// ===== impl ResponseHeadTimedOut =====
+
+impl ResponseHeadTimedOut {
+    /// The time the peer was quiet for.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+impl fmt::Display for ResponseHeadTimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "response head timed out: nothing was read from the peer for {:?}",
+            self.timeout
+        )
+    }
+}
+
+impl std::error::Error for ResponseHeadTimedOut {}
+
+// ===== impl Liveness =====
+
+impl Liveness {
+    pub(super) fn new() -> Self {
+        let activity = Activity {
+            in_flight: 0,
+            last_active: clock::now(),
+            expired: false,
+        };
+
+        Liveness {
+            activity: Arc::new(Mutex::new(activity)),
+        }
+    }
+
+    /// Record that a request was sent on the connection.
+    pub(super) fn request(&self) -> InFlight {
+        let mut activity = self.activity.lock().unwrap();
+        // The peer had no reason to send anything before this request.
+        if activity.in_flight == 0 {
+            activity.last_active = clock::now();
+        }
+        activity.in_flight += 1;
+
+        InFlight {
+            liveness: self.clone(),
+        }
+    }
+
+    fn read(&self) {
+        self.activity.lock().unwrap().last_active = clock::now();
+    }
+
+    /// When the connection times out, if a request is in flight.
+    fn deadline(&self, timeout: Duration) -> Option<Instant> {
+        let activity = self.activity.lock().unwrap();
+        if activity.in_flight == 0 {
+            return None;
+        }
+        Some(activity.last_active + timeout)
+    }
+
+    fn expire(&self) {
+        self.activity.lock().unwrap().expired = true;
+    }
+
+    fn is_expired(&self) -> bool {
+        self.activity.lock().unwrap().expired
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if let Ok(mut activity) = self.liveness.activity.lock() {
+            activity.in_flight -= 1;
+        }
+    }
+}
+
+// ===== impl LivenessIo =====
+
+impl<I> LivenessIo<I> {
+    pub(super) fn new(inner: I, liveness: Liveness) -> Self {
+        LivenessIo { inner, liveness }
+    }
+}
+
+impl<I: Read> Read for LivenessIo<I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Failing the IO lets hyper fail the requests in flight, which
+        // dropping the connection would not.
+        if self.liveness.is_expired() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "response head timed out",
+            ));
+        }
+
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.liveness.read();
+        }
+        Ok(n)
+    }
+}
+
+impl<I: Write> Write for LivenessIo<I> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<I: AsyncRead> AsyncRead for LivenessIo<I> {}
+
+impl<I: AsyncWrite> AsyncWrite for LivenessIo<I> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.inner.shutdown()
+    }
+}
+
+// ===== impl HeadTimeout =====
+
+impl HeadTimeout {
+    pub(super) fn new(liveness: Liveness, timeout: Duration) -> Self {
+        HeadTimeout {
+            liveness,
+            timeout,
+            delay: None,
+        }
+    }
+
+    /// Poll for the peer to have been quiet for longer than the timeout
+    /// while a request was in flight.
+    ///
+    /// Once expired, reads from the connection fail.
+    pub(super) fn poll_expired(&mut self) -> Async<ResponseHeadTimedOut> {
+        loop {
+            let at = match self.liveness.deadline(self.timeout) {
+                Some(at) => at,
+                None => return Async::NotReady,
+            };
+
+            let delay = self.delay.get_or_insert_with(|| Delay::new(at));
+            if delay.deadline() != at {
+                delay.reset(at);
+            }
+
+            match delay.poll() {
+                Ok(Async::NotReady) => return Async::NotReady,
+                Ok(Async::Ready(())) => {
+                    // Reads may have happened, or requests finished, while
+                    // the delay was pending.
+                    let deadline = self.liveness.deadline(self.timeout);
+                    if deadline.is_none_or(|at| at > clock::now()) {
+                        continue;
+                    }
+
+                    self.liveness.expire();
+                    let timeout = self.timeout;
+                    return Async::Ready(ResponseHeadTimedOut { timeout });
+                }
+                Err(e) => {
+                    // Without a timer the connection can still be used, it
+                    // just won't be checked.
+                    debug!("response head timer error: {}", e);
+                    return Async::NotReady;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use super::{ConnectError, Error as ClientError, ResponseHeadTimedOut};
+use crate::body::Replayable;
+use futures::future;
+use http::{Method, Request};
+use std::error::Error;
+use std::sync::Arc;
+use tower_retry::budget::Budget;
+use tower_retry::Policy;
+
+/// A retry `Policy` for requests sent over `Connection` or `Client`.
+///
+/// Requests are retried when they fail with an error that shows they were
+/// never sent, such as a request raced against a stale keep-alive connection
+/// being closed, or when their method is idempotent. Responses are never
+/// retried, whatever their status.
+///
+/// Retries are limited by a shared [`Budget`], which every request deposits
+/// into and every retry withdraws from, so that retries can not overload an
+/// already failing peer.
+///
+/// Request bodies must be [`Replayable`]. Requests whose body outgrew its
+/// buffer are not retried.
+///
+/// # Example
+///
+/// ```
+/// # use std::time::Duration;
+/// # use tower_hyper::client::RetryPolicy;
+/// # use tower_retry::budget::Budget;
+/// // Allow retrying 20% of requests, and at least 10 requests per second.
+/// let budget = Budget::new(Duration::from_secs(10), 10, 0.2);
+/// let policy = RetryPolicy::new(budget);
+/// ```
+///
+/// [`Budget`]: https://docs.rs/tower-retry/0.1/tower_retry/budget/struct.Budget.html
+/// [`Replayable`]: ../body/struct.Replayable.html
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    budget: Arc<Budget>,
+    /// Whether this policy is used for a retry, rather than the first
+    /// attempt of a request.
+    retry: bool,
+}
+
+/// Errors that `RetryPolicy` can classify.
+pub trait RetryableError {
+    /// Returns `true` if the request is known not to have been sent to the
+    /// peer, so it is safe to retry whatever its method.
+    fn is_unsent(&self) -> bool;
+}
+
+// ===== impl RetryPolicy =====
+
+impl RetryPolicy {
+    /// Create a new `RetryPolicy` limited by `budget`.
+    pub fn new(budget: Budget) -> Self {
+        RetryPolicy {
+            budget: Arc::new(budget),
+            retry: false,
+        }
+    }
+}
+
+impl<B, Res, E> Policy<Request<Replayable<B>>, Res, E> for RetryPolicy
+where
+    E: RetryableError,
+{
+    type Future = future::FutureResult<Self, ()>;
+
+    fn retry(
+        &self,
+        req: &Request<Replayable<B>>,
+        result: Result<&Res, &E>,
+    ) -> Option<Self::Future> {
+        if !self.retry {
+            self.budget.deposit();
+        }
+
+        let error = result.err()?;
+        if !error.is_unsent() && !is_idempotent(req.method()) {
+            return None;
+        }
+
+        if !req.body().is_replayable() {
+            return None;
+        }
+
+        if self.budget.withdraw().is_err() {
+            event!("retry budget exhausted");
+            return None;
+        }
+
+        let policy = RetryPolicy {
+            budget: self.budget.clone(),
+            retry: true,
+        };
+        Some(future::ok(policy))
+    }
+
+    fn clone_request(&self, req: &Request<Replayable<B>>) -> Option<Request<Replayable<B>>> {
+        let body = req.body().replay()?;
+
+        // Extensions can not be cloned, so they are only kept on the
+        // original request.
+        let mut clone = Request::new(body);
+        *clone.method_mut() = req.method().clone();
+        *clone.uri_mut() = req.uri().clone();
+        *clone.version_mut() = req.version();
+        *clone.headers_mut() = req.headers().clone();
+        Some(clone)
+    }
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    )
+}
+
+// ===== impl RetryableError =====
+
+impl RetryableError for hyper::Error {
+    /// Canceled requests were dropped before they were written, and closed
+    /// connections no longer accept requests.
+    fn is_unsent(&self) -> bool {
+        self.is_canceled() || self.is_closed()
+    }
+}
+
+impl<T> RetryableError for ConnectError<T> {
+    /// Requests are only sent once a connection is established.
+    fn is_unsent(&self) -> bool {
+        true
+    }
+}
+
+impl RetryableError for ClientError {
+    /// Canceled requests, and requests to connections that were retired or
+    /// whose background task failed, were never sent. Other errors are
+    /// classified by their source.
+    fn is_unsent(&self) -> bool {
+        match self {
+            ClientError::Canceled(_) | ClientError::Retired(_) | ClientError::Background(_) => true,
+            ClientError::ConnectionClosed(source) | ClientError::Timeout(source) => {
+                source.is_unsent()
+            }
+            ClientError::Protocol(_) | ClientError::BodyWrite(_) => false,
+        }
+    }
+}
+
+impl RetryableError for ResponseHeadTimedOut {
+    /// Connections that timed out no longer accept requests.
+    fn is_unsent(&self) -> bool {
+        true
+    }
+}
+
+impl RetryableError for crate::Error {
+    /// Boxed errors are classified if they are a `client::Error`, a
+    /// `hyper::Error` or a `ResponseHeadTimedOut`.
+    fn is_unsent(&self) -> bool {
+        let error: &(dyn Error + 'static) = &**self;
+        if let Some(e) = error.downcast_ref::<ClientError>() {
+            return e.is_unsent();
+        }
+        if let Some(e) = error.downcast_ref::<hyper::Error>() {
+            return e.is_unsent();
+        }
+        error.is::<ResponseHeadTimedOut>()
+    }
+}
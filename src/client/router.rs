@@ -0,0 +1,452 @@
+use super::{Connect, ConnectExecutor, ConnectFuture, Connection, Error, ResponseFuture};
+use crate::body::Body;
+use futures::task::{self, Task};
+use futures::{Async, Future, Poll};
+use http::header::{HeaderValue, HOST};
+use http::uri::{Authority, Parts, Scheme};
+use http::{Request, Response, Uri};
+use http_body::Body as HttpBody;
+use hyper::client::conn;
+use hyper::client::connect::Destination;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use tokio_timer::clock;
+use tower_http_util::connection::HttpMakeConnection;
+use tower_service::Service;
+
+/// Routes requests to a `Connection` per URI authority.
+///
+/// Requests must have an absolute URI. The first request to a scheme and
+/// authority connects to its [`Destination`] with a `Connect`, and later
+/// requests to the same scheme and authority reuse that connection.
+/// Connections that fail are evicted, and connections that were retired or
+/// closed by the peer are replaced, on the next request. Connections left
+/// unused for the [`idle_timeout`] are evicted too.
+///
+/// There is a single connection per scheme and authority. An HTTP/1
+/// connection sends one request at a time, so concurrent requests to the
+/// same authority wait for each other. Use connections that negotiate
+/// HTTP/2, or a `Balance` per authority, to send them concurrently.
+///
+/// Requests get a `Host` header if they have none. They are sent in origin
+/// form over HTTP/1, and with their absolute URI over connections whose
+/// transport negotiated HTTP/2.
+///
+/// Clones of a `Router` share their connections.
+///
+/// [`Destination`]: https://docs.rs/hyper/0.12/hyper/client/connect/struct.Destination.html
+/// [`idle_timeout`]: #method.idle_timeout
+pub struct Router<B, C, E>
+where
+    B: HttpBody,
+    C: HttpMakeConnection<Destination>,
+{
+    cache: Arc<Mutex<Cache<B, C, E>>>,
+}
+
+/// The future returned by `Router`.
+pub struct RouterFuture<B, C, E>
+where
+    B: HttpBody,
+    C: HttpMakeConnection<Destination>,
+{
+    cache: Arc<Mutex<Cache<B, C, E>>>,
+    state: State<B>,
+}
+
+enum State<B> {
+    /// Waiting for the connection to `key` to be ready.
+    ///
+    /// The request is boxed, as it is much larger than the other states.
+    Waiting(Key, Option<Box<Request<B>>>),
+    Sent(ResponseFuture<conn::ResponseFuture>),
+    Failed(Option<crate::Error>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Key {
+    scheme: Scheme,
+    authority: Authority,
+}
+
+struct Cache<B, C, E>
+where
+    B: HttpBody,
+    C: HttpMakeConnection<Destination>,
+{
+    connect: Connect<Destination, B, C, E>,
+    entries: HashMap<Key, Entry<B, C, E>>,
+    idle_timeout: Duration,
+}
+
+struct Entry<B, C, E>
+where
+    B: HttpBody,
+    C: HttpMakeConnection<Destination>,
+{
+    state: EntryState<B, C, E>,
+    /// Requests waiting for this connection. Only the last of them to poll
+    /// is notified by the connection, so it notifies the rest.
+    waiters: Vec<Task>,
+    /// When the entry was created, or last sent a request.
+    last_used: Instant,
+}
+
+enum EntryState<B, C, E>
+where
+    B: HttpBody,
+    C: HttpMakeConnection<Destination>,
+{
+    /// Waiting for `Connect` to be ready.
+    Idle,
+    /// Boxed, as the connect future is much larger than a connection.
+    Connecting(Box<ConnectFuture<Destination, B, C, E>>),
+    Connected(Connection<B>),
+}
+
+// ===== impl Router =====
+
+impl<B, C, E> Router<B, C, E>
+where
+    B: HttpBody,
+    C: HttpMakeConnection<Destination>,
+{
+    /// Route requests to connections created by `connect`.
+    pub fn new(connect: Connect<Destination, B, C, E>) -> Self {
+        let cache = Cache {
+            connect,
+            entries: HashMap::new(),
+            idle_timeout: Duration::from_secs(90),
+        };
+
+        Router {
+            cache: Arc::new(Mutex::new(cache)),
+        }
+    }
+
+    /// Evict connections that did not send a request for `timeout`.
+    ///
+    /// Evicted connections close once their requests in flight are done.
+    /// Idle connections are evicted when another request is routed, so
+    /// this bounds the connections kept to the authorities still in use.
+    ///
+    /// The default is 90 seconds.
+    pub fn idle_timeout(self, timeout: Duration) -> Self {
+        lock(&self.cache).idle_timeout = timeout;
+        self
+    }
+}
+
+impl<B, C, E> Service<Request<B>> for Router<B, C, E>
+where
+    C: HttpMakeConnection<Destination> + 'static,
+    C::Error: std::error::Error + Send + Sync + 'static,
+    C::Connection: Send + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<crate::Error>,
+    E: ConnectExecutor<C::Connection, B> + Clone,
+{
+    type Response = Response<Body>;
+    type Error = crate::Error;
+    type Future = RouterFuture<B, C, E>;
+
+    /// Always ready, as the connection a request needs is only known once
+    /// it is called. Requests wait for their connection in the returned
+    /// future instead.
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        let state = match Key::for_uri(req.uri()) {
+            Some(key) => {
+                if !req.headers().contains_key(HOST) {
+                    if let Ok(host) = HeaderValue::from_str(key.authority.as_str()) {
+                        req.headers_mut().insert(HOST, host);
+                    }
+                }
+                State::Waiting(key, Some(Box::new(req)))
+            }
+            None => {
+                let uri = req.uri().clone();
+                // Fails for URIs without a scheme or authority.
+                let e = Destination::try_from_uri(uri).err().map(Into::into);
+                State::Failed(e)
+            }
+        };
+
+        RouterFuture {
+            cache: self.cache.clone(),
+            state,
+        }
+    }
+}
+
+impl<B, C, E> Clone for Router<B, C, E>
+where
+    B: HttpBody,
+    C: HttpMakeConnection<Destination>,
+{
+    fn clone(&self) -> Self {
+        Router {
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<B, C, E> fmt::Debug for Router<B, C, E>
+where
+    B: HttpBody,
+    C: HttpMakeConnection<Destination>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Router")
+    }
+}
+
+// ===== impl RouterFuture =====
+
+impl<B, C, E> Future for RouterFuture<B, C, E>
+where
+    C: HttpMakeConnection<Destination> + 'static,
+    C::Error: std::error::Error + Send + Sync + 'static,
+    C::Connection: Send + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<crate::Error>,
+    E: ConnectExecutor<C::Connection, B> + Clone,
+{
+    type Item = Response<Body>;
+    type Error = crate::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let next = match &mut self.state {
+                State::Waiting(key, req) => {
+                    let mut cache = lock(&self.cache);
+                    match cache.poll_call(key, req)? {
+                        Async::Ready(fut) => State::Sent(fut),
+                        Async::NotReady => return Ok(Async::NotReady),
+                    }
+                }
+                State::Sent(fut) => {
+                    return match fut.poll() {
+                        Ok(Async::Ready(response)) => Ok(Async::Ready(response)),
+                        Ok(Async::NotReady) => Ok(Async::NotReady),
+                        Err(e) => Err(e.into()),
+                    };
+                }
+                State::Failed(e) => return Err(e.take().expect("polled after complete")),
+            };
+
+            self.state = next;
+        }
+    }
+}
+
+impl<B, C, E> Drop for RouterFuture<B, C, E>
+where
+    B: HttpBody,
+    C: HttpMakeConnection<Destination>,
+{
+    fn drop(&mut self) {
+        // The connection may only notify this future, so pass that on to
+        // the other requests waiting for it.
+        if let State::Waiting(key, _) = &self.state {
+            if let Some(entry) = lock(&self.cache).entries.get_mut(key) {
+                entry.notify_waiters();
+            }
+        }
+    }
+}
+
+impl<B, C, E> fmt::Debug for RouterFuture<B, C, E>
+where
+    B: HttpBody,
+    C: HttpMakeConnection<Destination>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("RouterFuture")
+    }
+}
+
+/// Lock the cache, even if a panic poisoned it. Entries are only replaced
+/// or removed as a whole, so they stay consistent.
+fn lock<B, C, E>(cache: &Mutex<Cache<B, C, E>>) -> MutexGuard<'_, Cache<B, C, E>>
+where
+    B: HttpBody,
+    C: HttpMakeConnection<Destination>,
+{
+    cache.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Strip the scheme and authority of `uri`, as HTTP/1 servers expect.
+fn origin_form(uri: &mut Uri) {
+    *uri = match uri.path_and_query() {
+        Some(path) => {
+            let mut parts = Parts::default();
+            parts.path_and_query = Some(path.clone());
+            Uri::from_parts(parts).expect("path is a valid URI")
+        }
+        None => Uri::default(),
+    };
+}
+
+// ===== impl Key =====
+
+impl Key {
+    fn for_uri(uri: &Uri) -> Option<Self> {
+        Some(Key {
+            scheme: uri.scheme_part()?.clone(),
+            authority: uri.authority_part()?.clone(),
+        })
+    }
+
+    fn destination(&self) -> Destination {
+        let uri = Uri::builder()
+            .scheme(self.scheme.clone())
+            .authority(self.authority.clone())
+            .path_and_query("/")
+            .build()
+            .expect("scheme and authority form a valid URI");
+        Destination::try_from_uri(uri).expect("URI has a scheme and authority")
+    }
+}
+
+// ===== impl Cache =====
+
+impl<B, C, E> Cache<B, C, E>
+where
+    C: HttpMakeConnection<Destination> + 'static,
+    C::Error: std::error::Error + Send + Sync + 'static,
+    C::Connection: Send + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<crate::Error>,
+    E: ConnectExecutor<C::Connection, B> + Clone,
+{
+    /// Send `req` once the connection to `key` is ready, connecting to it
+    /// if needed.
+    fn poll_call(
+        &mut self,
+        key: &Key,
+        req: &mut Option<Box<Request<B>>>,
+    ) -> Poll<ResponseFuture<conn::ResponseFuture>, crate::Error> {
+        let now = clock::now();
+        self.evict_idle(key, now);
+
+        let connect = &mut self.connect;
+        let entry = self.entries.entry(key.clone()).or_insert_with(|| Entry {
+            state: EntryState::Idle,
+            waiters: Vec::new(),
+            last_used: now,
+        });
+
+        match entry.poll_ready(connect, key) {
+            Ok(Async::Ready(())) => {}
+            Ok(Async::NotReady) => {
+                entry.waiters.push(task::current());
+                return Ok(Async::NotReady);
+            }
+            Err(e) => {
+                // Waiters connect again, or fail on their own.
+                entry.notify_waiters();
+                self.entries.remove(key);
+                return Err(e);
+            }
+        }
+
+        let mut req = *req.take().expect("polled after complete");
+        let fut = match &mut entry.state {
+            EntryState::Connected(connection) => {
+                if !connection.is_http2() {
+                    origin_form(req.uri_mut());
+                }
+                connection.call(req)
+            }
+            _ => unreachable!("ready entry is connected"),
+        };
+        entry.last_used = now;
+
+        // Waiters retry once this request took its turn.
+        entry.notify_waiters();
+        Ok(Async::Ready(fut))
+    }
+
+    /// Evict the entries other than `key` that are unused since the idle
+    /// timeout.
+    fn evict_idle(&mut self, key: &Key, now: Instant) {
+        let idle_timeout = self.idle_timeout;
+        self.entries.retain(|k, entry| {
+            let idle =
+                k != key && entry.waiters.is_empty() && entry.last_used + idle_timeout <= now;
+            if idle {
+                event!(authority = %k.authority, "evicting idle connection");
+            }
+            !idle
+        });
+    }
+}
+
+// ===== impl Entry =====
+
+impl<B, C, E> Entry<B, C, E>
+where
+    C: HttpMakeConnection<Destination> + 'static,
+    C::Error: std::error::Error + Send + Sync + 'static,
+    C::Connection: Send + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<crate::Error>,
+    E: ConnectExecutor<C::Connection, B> + Clone,
+{
+    fn poll_ready(
+        &mut self,
+        connect: &mut Connect<Destination, B, C, E>,
+        key: &Key,
+    ) -> Poll<(), crate::Error> {
+        loop {
+            let next = match &mut self.state {
+                EntryState::Idle => match connect.poll_ready() {
+                    Ok(Async::Ready(())) => {
+                        event!(authority = %key.authority, "connecting");
+                        EntryState::Connecting(Box::new(connect.call(key.destination())))
+                    }
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(e) => return Err(e.into()),
+                },
+                EntryState::Connecting(fut) => match fut.poll() {
+                    Ok(Async::Ready(connection)) => EntryState::Connected(connection),
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(e) => return Err(e.into()),
+                },
+                EntryState::Connected(connection) => match connection.poll_ready() {
+                    Ok(Async::Ready(())) => return Ok(Async::Ready(())),
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    // Nothing was sent on a connection that closed
+                    // gracefully, replace it like a retired one.
+                    Err(Error::Retired(_)) | Err(Error::ConnectionClosed(_)) => EntryState::Idle,
+                    Err(e) => return Err(e.into()),
+                },
+            };
+
+            self.state = next;
+        }
+    }
+}
+
+impl<B, C, E> Entry<B, C, E>
+where
+    B: HttpBody,
+    C: HttpMakeConnection<Destination>,
+{
+    fn notify_waiters(&mut self) {
+        for task in mem::take(&mut self.waiters) {
+            task.notify();
+        }
+    }
+}
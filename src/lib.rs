@@ -10,9 +10,22 @@
 //! This library is comprised of client and server modules. Currently, only
 //! the client portion is done and working. The server side is blocked partially
 //! by hypers use of its own Service and MakeService traits.
+//!
+//! # Features
+//!
+//! - `tracing`: emit [`tracing`] spans for server connections, server
+//!   requests, client connects and client requests.
+//! - `compression`: gzip, deflate and brotli response decompression for the
+//!   client, and gzip and brotli response compression for the server.
+//!
+//! [`tracing`]: https://docs.rs/tracing
+
+#[macro_use]
+mod trace;
 
 pub mod body;
 pub mod client;
+pub mod memory;
 pub mod server;
 pub mod util;
 
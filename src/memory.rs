@@ -0,0 +1,285 @@
+//! In-memory transports for testing services end to end
+//!
+//! [`duplex`] creates a connected pair of in-memory streams and [`channel`]
+//! creates a [`Connector`] that can be handed to [`client::Connect`] along with
+//! a [`Listener`] that yields the server side of each connection, ready to be
+//! passed to [`server::Server::serve`]. Together they allow full HTTP/1 and
+//! HTTP/2 stacks to be exercised without binding any sockets.
+//!
+//! # Example
+//!
+//! ```
+//! # use futures::{Future, Stream};
+//! # use tower_hyper::client::Connect;
+//! # use tower_hyper::memory;
+//! let (connector, listener) = memory::channel();
+//!
+//! // Each connection made through `connector` shows up on `listener`.
+//! let incoming = listener.for_each(|io| {
+//!     // server.serve(io) ...
+//!     # drop(io);
+//!     Ok(())
+//! });
+//!
+//! let connect = Connect::new(connector);
+//! # let _: Connect<(), Vec<u8>, memory::Connector, _> = connect;
+//! ```
+//!
+//! [`duplex`]: ./fn.duplex.html
+//! [`channel`]: ./fn.channel.html
+//! [`Connector`]: ./struct.Connector.html
+//! [`Listener`]: ./struct.Listener.html
+//! [`client::Connect`]: ../client/struct.Connect.html
+//! [`server::Server::serve`]: ../server/struct.Server.html#method.serve
+
+use futures::sync::mpsc;
+use futures::task::{self, Task};
+use futures::{future, Async, Poll, Stream};
+use http::Version;
+use http_connection::HttpConnection;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::{cmp, fmt};
+use tokio_io::{AsyncRead, AsyncWrite};
+use tower_service::Service;
+
+/// The default number of bytes buffered in each direction of a `Duplex`.
+const DEFAULT_MAX_BUF_SIZE: usize = 64 * 1024;
+
+/// One end of an in-memory, bidirectional byte stream.
+///
+/// Created by [`duplex`](./fn.duplex.html). Bytes written to one end can be
+/// read from the other. Dropping or shutting down one end causes reads on
+/// the other end to return EOF once the buffered bytes have been consumed.
+pub struct Duplex {
+    read: Arc<Mutex<Pipe>>,
+    write: Arc<Mutex<Pipe>>,
+}
+
+/// A `MakeConnection` that creates in-memory connections.
+///
+/// The client end of each connection is returned from `call` while the server
+/// end is yielded by the paired [`Listener`](./struct.Listener.html).
+#[derive(Clone, Debug)]
+pub struct Connector {
+    tx: mpsc::UnboundedSender<Duplex>,
+    max_buf_size: usize,
+}
+
+/// A stream of the server ends of connections made through a `Connector`.
+#[derive(Debug)]
+pub struct Listener {
+    rx: mpsc::UnboundedReceiver<Duplex>,
+}
+
+/// A single direction of a `Duplex`.
+#[derive(Debug)]
+struct Pipe {
+    buf: VecDeque<u8>,
+    max_buf_size: usize,
+    closed: bool,
+    read_task: Option<Task>,
+    write_task: Option<Task>,
+}
+
+/// Create a connected pair of in-memory streams.
+///
+/// Each direction buffers at most `max_buf_size` bytes, after which writes
+/// return `NotReady` until the other end reads.
+///
+/// # Panics
+///
+/// Panics if `max_buf_size` is zero, as no write could ever complete.
+pub fn duplex(max_buf_size: usize) -> (Duplex, Duplex) {
+    assert!(max_buf_size > 0, "max_buf_size must be greater than zero");
+
+    let a = Arc::new(Mutex::new(Pipe::new(max_buf_size)));
+    let b = Arc::new(Mutex::new(Pipe::new(max_buf_size)));
+
+    let one = Duplex {
+        read: a.clone(),
+        write: b.clone(),
+    };
+    let two = Duplex { read: b, write: a };
+
+    (one, two)
+}
+
+/// Create a `Connector` and the `Listener` that accepts its connections.
+pub fn channel() -> (Connector, Listener) {
+    let (tx, rx) = mpsc::unbounded();
+
+    let connector = Connector {
+        tx,
+        max_buf_size: DEFAULT_MAX_BUF_SIZE,
+    };
+
+    (connector, Listener { rx })
+}
+
+// ===== impl Duplex =====
+
+impl Read for Duplex {
+    fn read(&mut self, dst: &mut [u8]) -> io::Result<usize> {
+        let mut pipe = self.read.lock().unwrap();
+
+        if pipe.buf.is_empty() {
+            if pipe.closed {
+                return Ok(0);
+            }
+
+            pipe.read_task = Some(task::current());
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+
+        let n = cmp::min(dst.len(), pipe.buf.len());
+        for (dst, byte) in dst.iter_mut().zip(pipe.buf.drain(..n)) {
+            *dst = byte;
+        }
+
+        if let Some(task) = pipe.write_task.take() {
+            task.notify();
+        }
+
+        Ok(n)
+    }
+}
+
+impl Write for Duplex {
+    fn write(&mut self, src: &[u8]) -> io::Result<usize> {
+        let mut pipe = self.write.lock().unwrap();
+
+        if pipe.closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+
+        let available = pipe.max_buf_size - pipe.buf.len();
+        if available == 0 {
+            pipe.write_task = Some(task::current());
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+
+        let n = cmp::min(available, src.len());
+        pipe.buf.extend(&src[..n]);
+
+        if let Some(task) = pipe.read_task.take() {
+            task.notify();
+        }
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncRead for Duplex {}
+
+impl AsyncWrite for Duplex {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.write.lock().unwrap().close();
+        Ok(Async::Ready(()))
+    }
+}
+
+impl HttpConnection for Duplex {
+    fn negotiated_version(&self) -> Option<Version> {
+        None
+    }
+}
+
+impl Drop for Duplex {
+    fn drop(&mut self) {
+        if let Ok(mut pipe) = self.read.lock() {
+            pipe.close();
+        }
+
+        if let Ok(mut pipe) = self.write.lock() {
+            pipe.close();
+        }
+    }
+}
+
+impl fmt::Debug for Duplex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Duplex").finish()
+    }
+}
+
+// ===== impl Pipe =====
+
+impl Pipe {
+    fn new(max_buf_size: usize) -> Self {
+        Pipe {
+            buf: VecDeque::new(),
+            max_buf_size,
+            closed: false,
+            read_task: None,
+            write_task: None,
+        }
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+
+        if let Some(task) = self.read_task.take() {
+            task.notify();
+        }
+
+        if let Some(task) = self.write_task.take() {
+            task.notify();
+        }
+    }
+}
+
+// ===== impl Connector =====
+
+impl Connector {
+    /// Set the number of bytes buffered in each direction of new connections.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_buf_size` is zero, as no write could ever complete.
+    pub fn max_buf_size(mut self, max_buf_size: usize) -> Self {
+        assert!(max_buf_size > 0, "max_buf_size must be greater than zero");
+        self.max_buf_size = max_buf_size;
+        self
+    }
+}
+
+impl<A> Service<A> for Connector {
+    type Response = Duplex;
+    type Error = io::Error;
+    type Future = future::FutureResult<Duplex, io::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, _: A) -> Self::Future {
+        let (client, server) = duplex(self.max_buf_size);
+
+        let res =
+            self.tx.unbounded_send(server).map(|_| client).map_err(|_| {
+                io::Error::new(io::ErrorKind::ConnectionRefused, "listener was dropped")
+            });
+
+        future::result(res)
+    }
+}
+
+// ===== impl Listener =====
+
+impl Stream for Listener {
+    type Item = Duplex;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match self.rx.poll() {
+            Ok(item) => Ok(item),
+            Err(()) => unreachable!("unbounded receiver never errors"),
+        }
+    }
+}
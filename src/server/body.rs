@@ -0,0 +1,125 @@
+use super::panic::{self, PanicHook};
+use super::timeout::InFlight;
+use crate::body::LiftBody;
+use bytes::Buf;
+use futures::{Async, Poll};
+use http_body::Body as HttpBody;
+use hyper::body::Payload;
+use hyper::{Body, Chunk, HeaderMap};
+
+/// The response body handed to hyper by the server bridge.
+///
+/// Responses either come from the inner tower service or are produced
+/// locally by the bridge itself, for example when a request is rejected
+/// before it reaches the service.
+#[derive(Debug)]
+pub(super) struct ResponseBody<B> {
+    kind: Kind<B>,
+    /// Keeps the request counted as in flight until the body is dropped.
+    _in_flight: Option<InFlight>,
+    panic_hook: Option<PanicHook>,
+}
+
+#[derive(Debug)]
+pub(super) enum Kind<B> {
+    Lift(LiftBody<B>),
+    Local(Body),
+}
+
+/// The data chunks of a `ResponseBody`.
+#[derive(Debug)]
+pub(super) enum Data<D> {
+    Lift(D),
+    Local(Chunk),
+}
+
+impl<B> ResponseBody<B> {
+    pub(super) fn new(
+        kind: Kind<B>,
+        in_flight: Option<InFlight>,
+        panic_hook: Option<PanicHook>,
+    ) -> Self {
+        ResponseBody {
+            kind,
+            _in_flight: in_flight,
+            panic_hook,
+        }
+    }
+}
+
+impl<B> Payload for ResponseBody<B>
+where
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<crate::Error>,
+{
+    type Data = Data<B::Data>;
+    type Error = crate::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        match &mut self.kind {
+            Kind::Lift(body) => match panic::catch(&self.panic_hook, || Payload::poll_data(body)) {
+                Ok(Ok(Async::Ready(data))) => Ok(Async::Ready(data.map(Data::Lift))),
+                Ok(Ok(Async::NotReady)) => Ok(Async::NotReady),
+                Ok(Err(e)) => Err(e.into()),
+                // The headers are already sent, so all that is left is to
+                // abort the body.
+                Err(panic) => Err(Box::new(panic)),
+            },
+            Kind::Local(body) => match Payload::poll_data(body) {
+                Ok(Async::Ready(data)) => Ok(Async::Ready(data.map(Data::Local))),
+                Ok(Async::NotReady) => Ok(Async::NotReady),
+                Err(e) => Err(e.into()),
+            },
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        match &mut self.kind {
+            Kind::Lift(body) => {
+                match panic::catch(&self.panic_hook, || Payload::poll_trailers(body)) {
+                    Ok(poll) => poll.map_err(Into::into),
+                    Err(panic) => Err(Box::new(panic)),
+                }
+            }
+            Kind::Local(body) => Payload::poll_trailers(body).map_err(Into::into),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            Kind::Lift(body) => Payload::is_end_stream(body),
+            Kind::Local(body) => Payload::is_end_stream(body),
+        }
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        match &self.kind {
+            Kind::Lift(body) => Payload::content_length(body),
+            Kind::Local(body) => Payload::content_length(body),
+        }
+    }
+}
+
+impl<D: Buf> Buf for Data<D> {
+    fn remaining(&self) -> usize {
+        match self {
+            Data::Lift(buf) => buf.remaining(),
+            Data::Local(buf) => buf.remaining(),
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Data::Lift(buf) => buf.bytes(),
+            Data::Local(buf) => buf.bytes(),
+        }
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        match self {
+            Data::Lift(buf) => buf.advance(cnt),
+            Data::Local(buf) => buf.advance(cnt),
+        }
+    }
+}
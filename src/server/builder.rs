@@ -0,0 +1,221 @@
+use super::{Config, Http, Server};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// The largest HTTP/2 flow control window, `2^31 - 1`.
+const MAX_WINDOW_SIZE: u32 = (1 << 31) - 1;
+
+/// The HTTP/2 connection window starts at this size, and can only grow.
+const DEFAULT_CONNECTION_WINDOW_SIZE: u32 = 65_535;
+
+/// Builds a `Server` with custom HTTP/1 and HTTP/2 settings.
+///
+/// The settings are validated by `build`, rather than failing each
+/// connection later on.
+///
+/// hyper 0.12 does not expose HTTP/2 keep-alive pings, so they can not be
+/// configured here. Use `Server::idle_timeout` to close connections that go
+/// quiet.
+///
+/// # Example
+///
+/// ```
+/// # use futures::future;
+/// # use hyper::{Body, Request, Response};
+/// # use tower_hyper::server::Builder;
+/// # use tower_service::Service;
+/// # struct MakeSvc;
+/// # impl Service<()> for MakeSvc {
+/// #     type Response = Svc;
+/// #     type Error = hyper::Error;
+/// #     type Future = future::FutureResult<Svc, hyper::Error>;
+/// #     fn poll_ready(&mut self) -> futures::Poll<(), hyper::Error> { Ok(().into()) }
+/// #     fn call(&mut self, _: ()) -> Self::Future { future::ok(Svc) }
+/// # }
+/// # struct Svc;
+/// # impl Service<Request<Body>> for Svc {
+/// #     type Response = Response<Body>;
+/// #     type Error = hyper::Error;
+/// #     type Future = future::FutureResult<Response<Body>, hyper::Error>;
+/// #     fn poll_ready(&mut self) -> futures::Poll<(), hyper::Error> { Ok(().into()) }
+/// #     fn call(&mut self, _: Request<Body>) -> Self::Future { future::ok(Response::new(Body::empty())) }
+/// # }
+/// let server = Builder::new()
+///     .http2_initial_stream_window_size(1024 * 1024)
+///     .http2_initial_connection_window_size(4 * 1024 * 1024)
+///     .http2_max_concurrent_streams(256)
+///     .build(MakeSvc)
+///     .expect("valid settings");
+/// # let _: tower_hyper::server::Server<MakeSvc, Body> = server;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Builder {
+    http1_only: bool,
+    http1_keep_alive: Option<bool>,
+    http1_pipeline_flush: Option<bool>,
+    http2_only: bool,
+    http2_initial_stream_window_size: Option<u32>,
+    http2_initial_connection_window_size: Option<u32>,
+    http2_max_concurrent_streams: Option<u32>,
+}
+
+/// Error produced when a `Builder` has invalid settings.
+#[derive(Debug)]
+pub struct BuildError {
+    kind: Kind,
+}
+
+#[derive(Debug)]
+enum Kind {
+    ConflictingProtocols,
+    StreamWindowSize(u32),
+    ConnectionWindowSize(u32),
+    MaxConcurrentStreams,
+}
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Create a new `Builder` with hyper's default settings.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Only accept HTTP/1 connections.
+    pub fn http1_only(&mut self, enabled: bool) -> &mut Self {
+        self.http1_only = enabled;
+        self
+    }
+
+    /// Keep HTTP/1 connections open for more than one request.
+    ///
+    /// Enabled by default.
+    pub fn http1_keep_alive(&mut self, enabled: bool) -> &mut Self {
+        self.http1_keep_alive = Some(enabled);
+        self
+    }
+
+    /// Aggregate flushes of pipelined HTTP/1 responses.
+    ///
+    /// This is experimental in hyper, and disabled by default.
+    pub fn http1_pipeline_flush(&mut self, enabled: bool) -> &mut Self {
+        self.http1_pipeline_flush = Some(enabled);
+        self
+    }
+
+    /// Only accept HTTP/2 connections.
+    pub fn http2_only(&mut self, enabled: bool) -> &mut Self {
+        self.http2_only = enabled;
+        self
+    }
+
+    /// Set the initial HTTP/2 flow control window of each stream, in bytes.
+    ///
+    /// Must be at most `2^31 - 1`. Defaults to 65,535.
+    pub fn http2_initial_stream_window_size(&mut self, size: u32) -> &mut Self {
+        self.http2_initial_stream_window_size = Some(size);
+        self
+    }
+
+    /// Set the initial HTTP/2 flow control window of each connection, in
+    /// bytes.
+    ///
+    /// Must be between 65,535 and `2^31 - 1`. Defaults to 65,535.
+    pub fn http2_initial_connection_window_size(&mut self, size: u32) -> &mut Self {
+        self.http2_initial_connection_window_size = Some(size);
+        self
+    }
+
+    /// Set the maximum number of concurrent HTTP/2 streams per connection.
+    ///
+    /// Must be greater than zero. By default the number of streams is not
+    /// limited.
+    pub fn http2_max_concurrent_streams(&mut self, max: u32) -> &mut Self {
+        self.http2_max_concurrent_streams = Some(max);
+        self
+    }
+
+    /// Validate the settings and create a `Server` from a `MakeService`.
+    pub fn build<S, B>(&self, maker: S) -> Result<Server<S, B>, BuildError> {
+        let http = self.http()?;
+
+        Ok(Server {
+            maker,
+            config: Config::default(),
+            http,
+            _pd: PhantomData,
+        })
+    }
+
+    fn http(&self) -> Result<Http, BuildError> {
+        if self.http1_only && self.http2_only {
+            return Err(BuildError::new(Kind::ConflictingProtocols));
+        }
+
+        if let Some(size) = self.http2_initial_stream_window_size {
+            if size > MAX_WINDOW_SIZE {
+                return Err(BuildError::new(Kind::StreamWindowSize(size)));
+            }
+        }
+
+        if let Some(size) = self.http2_initial_connection_window_size {
+            if !(DEFAULT_CONNECTION_WINDOW_SIZE..=MAX_WINDOW_SIZE).contains(&size) {
+                return Err(BuildError::new(Kind::ConnectionWindowSize(size)));
+            }
+        }
+
+        if self.http2_max_concurrent_streams == Some(0) {
+            return Err(BuildError::new(Kind::MaxConcurrentStreams));
+        }
+
+        let mut http = Http::new();
+        http.http1_only(self.http1_only)
+            .http2_only(self.http2_only)
+            .http2_initial_stream_window_size(self.http2_initial_stream_window_size)
+            .http2_initial_connection_window_size(self.http2_initial_connection_window_size)
+            .http2_max_concurrent_streams(self.http2_max_concurrent_streams);
+
+        if let Some(enabled) = self.http1_keep_alive {
+            http.keep_alive(enabled);
+        }
+
+        if let Some(enabled) = self.http1_pipeline_flush {
+            http.pipeline_flush(enabled);
+        }
+
+        Ok(http)
+    }
+}
+
+// ===== impl BuildError =====
+
+impl BuildError {
+    fn new(kind: Kind) -> Self {
+        BuildError { kind }
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            Kind::ConflictingProtocols => {
+                f.write_str("http1_only and http2_only can not both be enabled")
+            }
+            Kind::StreamWindowSize(size) => write!(
+                f,
+                "HTTP/2 stream window size of {} exceeds the maximum of {}",
+                size, MAX_WINDOW_SIZE
+            ),
+            Kind::ConnectionWindowSize(size) => write!(
+                f,
+                "HTTP/2 connection window size of {} is not between {} and {}",
+                size, DEFAULT_CONNECTION_WINDOW_SIZE, MAX_WINDOW_SIZE
+            ),
+            Kind::MaxConcurrentStreams => {
+                f.write_str("HTTP/2 max concurrent streams must be greater than zero")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
@@ -0,0 +1,456 @@
+use flate2::write::GzEncoder;
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{
+    HeaderValue, ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE,
+    CONTENT_TYPE, VARY,
+};
+use http::{HeaderMap, Method, Request, Response, StatusCode};
+use http_body::Body as HttpBody;
+use hyper::Chunk;
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+use tokio_buf::SizeHint;
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Bodies known to be smaller than this are not compressed by default.
+const DEFAULT_MIN_SIZE: u64 = 1024;
+
+/// The buffer size of brotli encoders.
+const BROTLI_BUFFER_SIZE: usize = 4096;
+/// Favour speed over size, since responses are compressed on the fly.
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW_SIZE: u32 = 22;
+
+/// Compresses response bodies of the inner service.
+///
+/// This is meant to wrap the services made for a `Server`, and compresses
+/// responses with brotli or gzip, depending on the request's
+/// `Accept-Encoding` header. Bodies are compressed while they are streamed,
+/// flushing the encoder after every chunk of the inner body.
+///
+/// Responses are passed through unchanged if they:
+///
+/// - already have a `Content-Encoding`,
+/// - have a content type that is already compressed, like images, audio,
+///   video or archives,
+/// - are known to be smaller than the minimum size,
+/// - are partial content, with a `206` status or a `Content-Range`, as the
+///   range refers to the uncompressed representation,
+/// - have no body, or set `Cache-Control: no-transform`.
+///
+/// Responses that may be compressed get `Accept-Encoding` added to their
+/// `Vary` header, whether the request accepted compression or not, so caches
+/// keep compressed and uncompressed responses apart.
+///
+/// This requires the `compression` feature.
+#[derive(Clone, Debug)]
+pub struct Compress<S> {
+    inner: S,
+    min_size: u64,
+}
+
+/// A `Layer` that wraps services in `Compress`.
+#[derive(Clone, Debug)]
+pub struct CompressLayer {
+    min_size: u64,
+}
+
+/// The future returned by `Compress`.
+#[derive(Debug)]
+pub struct CompressFuture<F> {
+    inner: F,
+    encoding: Option<Encoding>,
+    /// Whether the response can have a body to compress.
+    has_body: bool,
+    min_size: u64,
+}
+
+/// A response body compressed by `Compress`.
+#[derive(Debug)]
+pub struct CompressBody<B> {
+    inner: B,
+    encoder: Option<Encoder>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+enum Encoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+}
+
+// ===== impl Compress =====
+
+impl<S> Compress<S> {
+    /// Wrap `inner`, compressing its response bodies.
+    pub fn new(inner: S) -> Self {
+        Compress {
+            inner,
+            min_size: DEFAULT_MIN_SIZE,
+        }
+    }
+
+    /// Set the size in bytes below which bodies are not compressed.
+    ///
+    /// Only bodies with a known length, from their size hint or their
+    /// `Content-Length` header, can be skipped.
+    ///
+    /// Defaults to 1024 bytes.
+    pub fn min_size(mut self, min_size: u64) -> Self {
+        self.min_size = min_size;
+        self
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Compress<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: HttpBody,
+{
+    type Response = Response<CompressBody<ResBody>>;
+    type Error = S::Error;
+    type Future = CompressFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        CompressFuture {
+            encoding: Encoding::negotiate(req.headers()),
+            has_body: req.method() != Method::HEAD,
+            min_size: self.min_size,
+            inner: self.inner.call(req),
+        }
+    }
+}
+
+// ===== impl CompressLayer =====
+
+impl CompressLayer {
+    /// Create a new `CompressLayer`.
+    pub fn new() -> Self {
+        CompressLayer {
+            min_size: DEFAULT_MIN_SIZE,
+        }
+    }
+
+    /// Set the size in bytes below which bodies are not compressed.
+    pub fn min_size(mut self, min_size: u64) -> Self {
+        self.min_size = min_size;
+        self
+    }
+}
+
+impl Default for CompressLayer {
+    fn default() -> Self {
+        CompressLayer::new()
+    }
+}
+
+impl<S> Layer<S> for CompressLayer {
+    type Service = Compress<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Compress::new(inner).min_size(self.min_size)
+    }
+}
+
+// ===== impl CompressFuture =====
+
+impl<F, B> CompressFuture<F>
+where
+    F: Future<Item = Response<B>>,
+    B: HttpBody,
+{
+    /// Returns `true` if `response` may be compressed, whatever the request
+    /// accepts.
+    fn is_compressible(&self, response: &Response<B>) -> bool {
+        let status = response.status();
+        if !self.has_body || status == StatusCode::NO_CONTENT || status == StatusCode::NOT_MODIFIED
+        {
+            return false;
+        }
+
+        let headers = response.headers();
+        if status == StatusCode::PARTIAL_CONTENT
+            || headers.contains_key(CONTENT_RANGE)
+            || headers.contains_key(CONTENT_ENCODING)
+            || contains_token(headers, CACHE_CONTROL, "no-transform")
+        {
+            return false;
+        }
+
+        let compressed_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(is_compressed_type)
+            .unwrap_or(false);
+        if compressed_type {
+            return false;
+        }
+
+        let hint = response.body().size_hint();
+        let len = match hint.upper() {
+            Some(upper) if upper == hint.lower() => Some(upper),
+            _ => headers
+                .get(CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok()),
+        };
+
+        len.map(|len| len >= self.min_size).unwrap_or(true)
+    }
+}
+
+impl<F, B> Future for CompressFuture<F>
+where
+    F: Future<Item = Response<B>>,
+    B: HttpBody,
+{
+    type Item = Response<CompressBody<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut response = try_ready!(self.inner.poll());
+
+        let encoder = if self.is_compressible(&response) {
+            let headers = response.headers_mut();
+            if !contains_token(headers, VARY, "accept-encoding")
+                && !contains_token(headers, VARY, "*")
+            {
+                headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+            }
+
+            self.encoding.map(|encoding| {
+                headers.insert(CONTENT_ENCODING, encoding.header_value());
+                headers.remove(CONTENT_LENGTH);
+                Encoder::new(encoding)
+            })
+        } else {
+            None
+        };
+
+        let response = response.map(|inner| CompressBody { inner, encoder });
+        Ok(Async::Ready(response))
+    }
+}
+
+// ===== impl CompressBody =====
+
+impl<B> HttpBody for CompressBody<B>
+where
+    B: HttpBody,
+    B::Data: Into<Chunk>,
+    B::Error: Into<crate::Error>,
+{
+    type Data = Chunk;
+    type Error = crate::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        loop {
+            let data = match self.inner.poll_data() {
+                Ok(Async::Ready(data)) => data,
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(e) => return Err(e.into()),
+            };
+
+            let encoder = match &mut self.encoder {
+                Some(encoder) => encoder,
+                None => return Ok(Async::Ready(data.map(Into::into))),
+            };
+
+            let encoded = match data {
+                Some(data) => {
+                    let chunk: Chunk = data.into();
+                    encoder.encode(&chunk)?
+                }
+                None => {
+                    // The body is done, write the end of the stream.
+                    let encoder = self.encoder.take().expect("encoder");
+                    let encoded = encoder.finish()?;
+                    if encoded.is_empty() {
+                        return Ok(Async::Ready(None));
+                    }
+                    encoded
+                }
+            };
+
+            if !encoded.is_empty() {
+                return Ok(Async::Ready(Some(Chunk::from(encoded))));
+            }
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        self.inner.poll_trailers().map_err(Into::into)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.encoder.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self.encoder {
+            // The compressed length is unknown.
+            Some(_) => SizeHint::default(),
+            None => self.inner.size_hint(),
+        }
+    }
+}
+
+// ===== impl Encoding =====
+
+impl Encoding {
+    /// Pick the preferred encoding accepted by the request, favouring brotli
+    /// when both are equally acceptable.
+    ///
+    /// A `*` only applies to codings not listed explicitly, so codings
+    /// refused with `q=0` stay refused.
+    fn negotiate(headers: &HeaderMap) -> Option<Self> {
+        let mut brotli = None;
+        let mut gzip = None;
+        let mut any = None;
+
+        for value in headers.get_all(ACCEPT_ENCODING).iter() {
+            let value = match value.to_str() {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+
+            for item in value.split(',') {
+                let mut parts = item.split(';');
+                let coding = parts.next().unwrap_or("").trim();
+                let q = parts
+                    .filter_map(|param| param.trim().strip_prefix("q=")?.parse::<f32>().ok())
+                    .next()
+                    .unwrap_or(1.0);
+
+                if coding.eq_ignore_ascii_case("br") {
+                    brotli = Some(q);
+                } else if coding.eq_ignore_ascii_case("gzip") {
+                    gzip = Some(q);
+                } else if coding == "*" {
+                    any = Some(q);
+                }
+            }
+        }
+
+        let brotli = brotli.or(any).unwrap_or(0.0);
+        let gzip = gzip.or(any).unwrap_or(0.0);
+
+        if brotli <= 0.0 && gzip <= 0.0 {
+            None
+        } else if brotli >= gzip {
+            Some(Encoding::Brotli)
+        } else {
+            Some(Encoding::Gzip)
+        }
+    }
+
+    fn header_value(self) -> HeaderValue {
+        match self {
+            Encoding::Brotli => HeaderValue::from_static("br"),
+            Encoding::Gzip => HeaderValue::from_static("gzip"),
+        }
+    }
+}
+
+// ===== impl Encoder =====
+
+impl Encoder {
+    fn new(encoding: Encoding) -> Self {
+        match encoding {
+            Encoding::Gzip => {
+                let encoder = GzEncoder::new(Vec::new(), flate2::Compression::fast());
+                Encoder::Gzip(encoder)
+            }
+            Encoding::Brotli => {
+                let encoder = brotli::CompressorWriter::new(
+                    Vec::new(),
+                    BROTLI_BUFFER_SIZE,
+                    BROTLI_QUALITY,
+                    BROTLI_WINDOW_SIZE,
+                );
+                Encoder::Brotli(Box::new(encoder))
+            }
+        }
+    }
+
+    /// Compress `input`, flushing so that it can be sent right away.
+    fn encode(&mut self, input: &[u8]) -> io::Result<Vec<u8>> {
+        let output = match self {
+            Encoder::Gzip(encoder) => {
+                encoder.write_all(input)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+            Encoder::Brotli(encoder) => {
+                encoder.write_all(input)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+        };
+
+        Ok(mem::take(output))
+    }
+
+    /// Finish the stream, returning the remaining output.
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Brotli(encoder) => Ok(encoder.into_inner()),
+        }
+    }
+}
+
+impl fmt::Debug for Encoder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Encoder::Gzip(_) => f.write_str("Gzip"),
+            Encoder::Brotli(_) => f.write_str("Brotli"),
+        }
+    }
+}
+
+/// Returns `true` if any of the comma separated values of `name` is `token`.
+fn contains_token(headers: &HeaderMap, name: http::header::HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(token))
+}
+
+/// Returns `true` for content types whose data is already compressed.
+fn is_compressed_type(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+
+    if essence == "image/svg+xml" {
+        return false;
+    }
+
+    essence.starts_with("image/")
+        || essence.starts_with("audio/")
+        || essence.starts_with("video/")
+        || essence == "font/woff"
+        || essence == "font/woff2"
+        || essence == "application/zip"
+        || essence == "application/gzip"
+        || essence == "application/x-gzip"
+        || essence == "application/x-bzip2"
+        || essence == "application/x-7z-compressed"
+        || essence == "application/x-rar-compressed"
+}
@@ -0,0 +1,126 @@
+use super::LengthLimitError;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+
+/// Error's produced by a `Connection`.
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The client violated the HTTP protocol.
+    Protocol(hyper::Error),
+    /// Reading from or writing to the connection failed, for example
+    /// because the client disconnected.
+    Io(hyper::Error),
+    /// The handshake passed to `Server::serve_handshake` failed, such as a
+    /// TLS handshake.
+    Handshake(crate::Error),
+    /// Error's produced from creating the inner service.
+    MakeService(E),
+    /// The inner service failed a request, or its response body failed
+    /// while being written.
+    Service(hyper::Error),
+    /// A request body exceeded the configured maximum size, and the inner
+    /// service failed with the resulting `LengthLimitError`.
+    BodyLimit(hyper::Error),
+    /// The client did not finish sending a request head within the
+    /// configured header read timeout.
+    HeaderTimeout,
+    /// The connection had no request in flight and read no bytes within the
+    /// configured idle timeout.
+    IdleTimeout,
+}
+
+// ===== impl Error =====
+
+impl<E> Error<E> {
+    /// Classify an error returned by hyper while serving a connection.
+    pub(super) fn serve(error: hyper::Error) -> Self {
+        if caused_by::<LengthLimitError>(&error) {
+            Error::BodyLimit(error)
+        } else if error.is_user() {
+            Error::Service(error)
+        } else if error.is_incomplete_message() || caused_by::<io::Error>(&error) {
+            Error::Io(error)
+        } else {
+            Error::Protocol(error)
+        }
+    }
+
+    /// Map the `MakeService` error with `f`.
+    pub(super) fn map_make_service<F>(self, f: impl FnOnce(E) -> F) -> Error<F> {
+        match self {
+            Error::Protocol(e) => Error::Protocol(e),
+            Error::Io(e) => Error::Io(e),
+            Error::Handshake(e) => Error::Handshake(e),
+            Error::MakeService(e) => Error::MakeService(f(e)),
+            Error::Service(e) => Error::Service(e),
+            Error::BodyLimit(e) => Error::BodyLimit(e),
+            Error::HeaderTimeout => Error::HeaderTimeout,
+            Error::IdleTimeout => Error::IdleTimeout,
+        }
+    }
+
+    /// Returns `true` if the error is part of normal operation, such as a
+    /// client disconnecting or an idle connection being closed, rather than
+    /// something worth logging.
+    pub fn is_benign(&self) -> bool {
+        match self {
+            Error::Io(e) => e.is_incomplete_message() || io_error(e).is_some_and(is_disconnect),
+            Error::IdleTimeout => true,
+            _ => false,
+        }
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Protocol(e) => write!(f, "HTTP protocol error: {}", e),
+            Error::Io(e) => write!(f, "connection IO error: {}", e),
+            Error::Handshake(e) => write!(f, "handshake failed: {}", e),
+            Error::MakeService(e) => write!(f, "error making service: {:?}", e),
+            Error::Service(e) => write!(f, "service error: {}", e),
+            Error::BodyLimit(e) => write!(f, "request body too large: {}", e),
+            Error::HeaderTimeout => f.write_str("request header read timed out"),
+            Error::IdleTimeout => f.write_str("connection idle timed out"),
+        }
+    }
+}
+
+impl<E: fmt::Debug> StdError for Error<E> {
+    /// The source of `MakeService` errors is not available, as the error
+    /// type is not required to implement `Error`.
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Protocol(e) | Error::Io(e) | Error::Service(e) | Error::BodyLimit(e) => Some(e),
+            Error::Handshake(e) => Some(&**e),
+            Error::MakeService(_) | Error::HeaderTimeout | Error::IdleTimeout => None,
+        }
+    }
+}
+
+/// Returns `true` if an error of type `T` is in the source chain of `error`.
+fn caused_by<T: StdError + 'static>(error: &hyper::Error) -> bool {
+    let mut source = error.source();
+    while let Some(e) = source {
+        if e.is::<T>() {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+fn io_error(error: &hyper::Error) -> Option<&io::Error> {
+    error.source()?.downcast_ref::<io::Error>()
+}
+
+fn is_disconnect(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::UnexpectedEof
+    )
+}
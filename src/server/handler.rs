@@ -0,0 +1,70 @@
+use hyper::{Body, Response};
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+/// Maps errors from the inner service's response future to responses.
+///
+/// Without an error handler, or when it returns `None`, a failed response
+/// future fails the request, which resets the stream on HTTP/2 and closes the
+/// connection on HTTP/1. Returning a response instead lets clients see an
+/// HTTP error such as `503 Service Unavailable` for overload or
+/// `504 Gateway Timeout` for timeouts.
+///
+/// This is implemented for closures taking the error and returning an
+/// optional response.
+///
+/// # Example
+///
+/// ```
+/// # use hyper::{Body, Response};
+/// # use http::StatusCode;
+/// # use std::error::Error;
+/// # use tower_hyper::server::ErrorHandler;
+/// fn unavailable(_: &(dyn Error + Send + Sync + 'static)) -> Option<Response<Body>> {
+///     let mut response = Response::new(Body::empty());
+///     *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+///     Some(response)
+/// }
+/// # fn assert_handler<H: ErrorHandler>(_: H) {}
+/// # assert_handler(unavailable);
+/// ```
+pub trait ErrorHandler {
+    /// Returns the response to send for `error`, or `None` to fail the
+    /// request.
+    fn handle(&self, error: &(dyn Error + Send + Sync + 'static)) -> Option<Response<Body>>;
+}
+
+/// The error handler registered with `Server::error_handler`.
+#[derive(Clone)]
+pub(super) struct Handler(Arc<dyn ErrorHandler + Send + Sync>);
+
+impl<F> ErrorHandler for F
+where
+    F: Fn(&(dyn Error + Send + Sync + 'static)) -> Option<Response<Body>>,
+{
+    fn handle(&self, error: &(dyn Error + Send + Sync + 'static)) -> Option<Response<Body>> {
+        self(error)
+    }
+}
+
+// ===== impl Handler =====
+
+impl Handler {
+    pub(super) fn new<H>(handler: H) -> Self
+    where
+        H: ErrorHandler + Send + Sync + 'static,
+    {
+        Handler(Arc::new(handler))
+    }
+
+    pub(super) fn handle(&self, error: &crate::Error) -> Option<Response<Body>> {
+        self.0.handle(&**error)
+    }
+}
+
+impl fmt::Debug for Handler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ErrorHandler")
+    }
+}
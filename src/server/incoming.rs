@@ -0,0 +1,149 @@
+use super::{Error, Server};
+use crate::body::Body;
+use futures::task::AtomicTask;
+use futures::{try_ready, Async, Future, Poll, Stream};
+use http_body::Body as HttpBody;
+use hyper::{Request, Response};
+use log::{debug, trace};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio_executor::{DefaultExecutor, TypedExecutor};
+use tokio_io::{AsyncRead, AsyncWrite};
+use tower_service::Service;
+use tower_util::MakeService;
+
+/// What the accept loop does once the connection limit is reached.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LimitStrategy {
+    /// Stop accepting new connections until a served connection closes.
+    ///
+    /// Pending connections queue up in the listener's backlog.
+    #[default]
+    Pause,
+    /// Keep accepting connections, but close them immediately.
+    Reject,
+}
+
+/// A future that serves every connection from a stream of IOs.
+///
+/// Created by [`Server::serve_incoming`].
+///
+/// [`Server::serve_incoming`]: ./struct.Server.html#method.serve_incoming
+#[derive(Debug)]
+pub struct Incoming<St, S, B> {
+    incoming: St,
+    server: Server<S, B>,
+    connections: Arc<Connections>,
+}
+
+/// The number of connections currently served by an `Incoming`.
+#[derive(Debug)]
+struct Connections {
+    active: AtomicUsize,
+    task: AtomicTask,
+}
+
+/// Counts a connection as active until dropped.
+#[derive(Debug)]
+struct Active(Arc<Connections>);
+
+// ===== impl Incoming =====
+
+impl<St, S, B> Incoming<St, S, B> {
+    pub(super) fn new(incoming: St, server: Server<S, B>) -> Self {
+        let connections = Connections {
+            active: AtomicUsize::new(0),
+            task: AtomicTask::new(),
+        };
+
+        Incoming {
+            incoming,
+            server,
+            connections: Arc::new(connections),
+        }
+    }
+
+    /// Returns `true` if a new connection would exceed the limit.
+    fn at_limit(&self) -> bool {
+        match self.server.config.max_connections {
+            Some(max) => self.connections.active.load(Ordering::SeqCst) >= max,
+            None => false,
+        }
+    }
+}
+
+impl<St, S, B> Future for Incoming<St, S, B>
+where
+    St: Stream,
+    St::Item: AsyncRead + AsyncWrite + Send + 'static,
+    S: MakeService<(), Request<Body>, Response = Response<B>> + Send + 'static,
+    S::MakeError: Into<crate::Error>,
+    S::Error: Into<crate::Error>,
+    S::Future: Send,
+    S::Service: Service<Request<Body>> + Send,
+    <S::Service as Service<Request<Body>>>::Future: Send + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send + 'static,
+    B::Error: Into<crate::Error> + 'static,
+{
+    type Item = ();
+    type Error = St::Error;
+
+    fn poll(&mut self) -> Poll<(), Self::Error> {
+        let strategy = self.server.config.limit_strategy;
+
+        loop {
+            // Register before checking so a connection closing in between
+            // still wakes up the accept loop.
+            self.connections.task.register();
+
+            if strategy == LimitStrategy::Pause && self.at_limit() {
+                return Ok(Async::NotReady);
+            }
+
+            let io = match try_ready!(self.incoming.poll()) {
+                Some(io) => io,
+                None => return Ok(Async::Ready(())),
+            };
+
+            if self.at_limit() {
+                debug!("connection limit reached; rejecting connection");
+                event!("connection rejected");
+                drop(io);
+                continue;
+            }
+
+            self.connections.active.fetch_add(1, Ordering::SeqCst);
+            let active = Active(self.connections.clone());
+
+            let serve = self.server.serve(io);
+            let fut = serve.then(move |res| -> Result<(), ()> {
+                drop(active);
+
+                if let Err(e) = res {
+                    let e: Error<crate::Error> = e.map_make_service(Into::into);
+                    if e.is_benign() {
+                        trace!("connection closed: {}", e);
+                    } else {
+                        debug!("error serving connection: {}", e);
+                    }
+                }
+
+                Ok(())
+            });
+
+            if let Err(e) = DefaultExecutor::current().spawn(fut) {
+                debug!("error spawning connection task: {:?}", e);
+            }
+        }
+    }
+}
+
+// ===== impl Active =====
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::SeqCst);
+        self.0.task.notify();
+    }
+}
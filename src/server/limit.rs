@@ -0,0 +1,103 @@
+use futures::{try_ready, Async, Poll, Stream};
+use http::header::{HeaderValue, CONNECTION, CONTENT_LENGTH};
+use http::{HeaderMap, StatusCode, Version};
+use hyper::{Body, Chunk, Response};
+use std::fmt;
+
+/// Error produced when a request body exceeds the configured limit.
+///
+/// When a chunked request body overruns the limit set with
+/// `Server::max_request_body_size`, the request body stream seen by the
+/// service ends with a `hyper::Error` whose cause is this error.
+#[derive(Debug)]
+pub struct LengthLimitError {
+    limit: usize,
+}
+
+/// Stream of request body chunks that errors once `limit` bytes are exceeded.
+#[derive(Debug)]
+pub(super) struct Limited {
+    inner: Body,
+    remaining: usize,
+    limit: usize,
+}
+
+/// Returns `true` if the request declares a `Content-Length` above `limit`.
+pub(super) fn exceeds_limit(headers: &HeaderMap, limit: usize) -> bool {
+    content_length(headers)
+        .map(|len| len > limit as u64)
+        .unwrap_or(false)
+}
+
+/// Returns the `Content-Length` declared in `headers`, if any.
+pub(super) fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+/// Builds the `413 Payload Too Large` response sent for oversized requests.
+pub(super) fn payload_too_large(version: Version) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+
+    // The unread body is still on the wire, so an HTTP/1 connection
+    // can not be reused for another request.
+    if version < Version::HTTP_2 {
+        response
+            .headers_mut()
+            .insert(CONNECTION, HeaderValue::from_static("close"));
+    }
+
+    response
+}
+
+// ===== impl LengthLimitError =====
+
+impl LengthLimitError {
+    /// The maximum number of bytes a request body could contain.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+impl fmt::Display for LengthLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "request body exceeded the limit of {} bytes", self.limit)
+    }
+}
+
+impl std::error::Error for LengthLimitError {}
+
+// ===== impl Limited =====
+
+impl Limited {
+    pub(super) fn new(inner: Body, limit: usize) -> Self {
+        Limited {
+            inner,
+            remaining: limit,
+            limit,
+        }
+    }
+}
+
+impl Stream for Limited {
+    type Item = Chunk;
+    type Error = crate::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match try_ready!(self.inner.poll()) {
+            Some(chunk) => {
+                if chunk.len() > self.remaining {
+                    let limit = self.limit;
+                    return Err(Box::new(LengthLimitError { limit }));
+                }
+
+                self.remaining -= chunk.len();
+                Ok(Async::Ready(Some(chunk)))
+            }
+            None => Ok(Async::Ready(None)),
+        }
+    }
+}
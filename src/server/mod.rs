@@ -1,12 +1,41 @@
 //! The server porition of tower hyper
 
+mod body;
+mod builder;
+#[cfg(feature = "compression")]
+mod compress;
+mod error;
+mod handler;
+mod incoming;
+mod limit;
+mod panic;
+mod shed;
+mod timeout;
+
+pub use self::builder::{BuildError, Builder};
+#[cfg(feature = "compression")]
+pub use self::compress::{Compress, CompressBody, CompressFuture, CompressLayer};
+pub use self::error::Error;
+pub use self::handler::ErrorHandler;
+pub use self::incoming::{Incoming, LimitStrategy};
+pub use self::limit::LengthLimitError;
+pub use self::panic::Panic;
+
+use self::body::{Kind, ResponseBody};
+use self::handler::Handler;
+use self::limit::Limited;
+use self::panic::PanicHook;
+use self::timeout::{InFlight, Timeouts, TrackedIo, Tracker};
 use crate::body::{Body, LiftBody};
-use futures::{try_ready, Future, Poll};
+use crate::trace::{Instrumented, Span};
+use futures::future::Either;
+use futures::{Async, Future, Poll, Stream};
 use http_body::Body as HttpBody;
 use hyper::service::Service as HyperService;
 use hyper::{Request, Response};
-use std::fmt;
 use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::time::Duration;
 use tokio_io::{AsyncRead, AsyncWrite};
 use tower_http_util::service::HttpService;
 use tower_service::Service;
@@ -21,30 +50,53 @@ pub type Serve<E> = Box<dyn Future<Item = (), Error = Error<E>> + Send + 'static
 #[derive(Debug)]
 pub struct Server<S, B> {
     maker: S,
+    config: Config,
+    http: Http,
     _pd: PhantomData<B>,
 }
 
-/// Error's produced by a `Connection`.
-#[derive(Debug)]
-pub enum Error<E> {
-    /// Error's originating from `hyper`.
-    Protocol(hyper::Error),
-    /// Error's produced from creating the inner service.
-    MakeService(E),
+/// Settings shared by every connection served by a `Server`.
+#[derive(Clone, Debug, Default)]
+struct Config {
+    max_request_body_size: Option<usize>,
+    header_read_timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    max_connections: Option<usize>,
+    limit_strategy: LimitStrategy,
+    panic_hook: Option<PanicHook>,
+    error_handler: Option<Handler>,
+    shed_load: Option<Duration>,
 }
 
 #[derive(Debug)]
 struct LiftService<T, B> {
     inner: T,
+    config: Config,
+    tracker: Option<Tracker>,
+    /// Whether the inner service reported ready since the last call, only
+    /// tracked when shedding load.
+    ready: bool,
     _pd: PhantomData<B>,
 }
 
 #[derive(Debug)]
 struct LiftServiceFuture<F, B> {
-    inner: F,
+    state: State<F>,
+    in_flight: Option<InFlight>,
+    panic_hook: Option<PanicHook>,
+    error_handler: Option<Handler>,
+    span: Span,
     _pd: PhantomData<B>,
 }
 
+#[derive(Debug)]
+enum State<F> {
+    /// Waiting on the inner service's response.
+    Inner(F),
+    /// A response produced by the bridge without calling the inner service.
+    Local(Option<Response<Body>>),
+}
+
 impl<S, B> Server<S, B>
 where
     S: MakeService<(), Request<Body>, Response = Response<B>> + Send + 'static,
@@ -61,41 +113,271 @@ where
     pub fn new(maker: S) -> Self {
         Server {
             maker,
+            config: Config::default(),
+            http: Http::new(),
             _pd: PhantomData,
         }
     }
 
-    /// Serve the `io` stream via default hyper http settings
+    /// Set the maximum size in bytes of request bodies.
+    ///
+    /// Requests that declare a `Content-Length` above the limit are answered
+    /// with `413 Payload Too Large` without calling the inner service. Bodies
+    /// of unknown length are counted while they are streamed to the service,
+    /// and fail with a [`LengthLimitError`] once they overrun the limit.
+    ///
+    /// By default request bodies are not limited.
+    ///
+    /// [`LengthLimitError`]: ./struct.LengthLimitError.html
+    pub fn max_request_body_size(&mut self, limit: usize) -> &mut Self {
+        self.config.max_request_body_size = Some(limit);
+        self
+    }
+
+    /// Set how long a client may take to send a complete request head.
+    ///
+    /// The deadline starts when the first byte of a request is read and
+    /// connections that exceed it are closed with `Error::HeaderTimeout`.
+    /// This guards against clients that trickle headers to hold connections
+    /// open. HTTP/2 connections are not subject to this timeout.
+    ///
+    /// By default there is no header read timeout.
+    pub fn header_read_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.header_read_timeout = Some(timeout);
+        self
+    }
+
+    /// Set how long a connection may stay open without a request in flight
+    /// and without reading any bytes.
+    ///
+    /// Connections that exceed it are closed with `Error::IdleTimeout`.
+    ///
+    /// By default there is no idle timeout.
+    pub fn idle_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the maximum number of connections served at once by
+    /// `serve_incoming`.
+    ///
+    /// Once the limit is reached the accept loop either pauses or rejects new
+    /// connections, depending on the [`LimitStrategy`], until a served
+    /// connection closes. Connections passed directly to `serve` are not
+    /// counted.
+    ///
+    /// By default the number of connections is not limited.
+    ///
+    /// [`LimitStrategy`]: ./enum.LimitStrategy.html
+    pub fn max_connections(&mut self, max: usize) -> &mut Self {
+        self.config.max_connections = Some(max);
+        self
+    }
+
+    /// Set what the accept loop does once `max_connections` is reached.
+    ///
+    /// Defaults to `LimitStrategy::Pause`.
+    pub fn limit_strategy(&mut self, strategy: LimitStrategy) -> &mut Self {
+        self.config.limit_strategy = strategy;
+        self
+    }
+
+    /// Catch panics raised by the inner service and report them to `hook`.
+    ///
+    /// Panics while calling the service or polling its response future are
+    /// answered with `500 Internal Server Error`. Panics while streaming the
+    /// response body happen after the headers were sent, so they fail the
+    /// body instead, which closes HTTP/1 connections and resets HTTP/2
+    /// streams.
+    ///
+    /// By default panics are not caught and tear down the connection task.
+    pub fn catch_panics<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&Panic) + Send + Sync + 'static,
+    {
+        self.config.panic_hook = Some(PanicHook::new(hook));
+        self
+    }
+
+    /// Set the [`ErrorHandler`] used to turn errors from the inner service's
+    /// response future into responses.
+    ///
+    /// By default such errors fail the request, which resets the stream on
+    /// HTTP/2 and closes the connection on HTTP/1.
+    ///
+    /// [`ErrorHandler`]: ./trait.ErrorHandler.html
+    pub fn error_handler<H>(&mut self, handler: H) -> &mut Self
+    where
+        H: ErrorHandler + Send + Sync + 'static,
+    {
+        self.config.error_handler = Some(Handler::new(handler));
+        self
+    }
+
+    /// Answer requests with `503 Service Unavailable` while the inner service
+    /// is not ready, instead of waiting for it to become ready.
+    ///
+    /// Waiting applies backpressure to the connection: hyper stops reading
+    /// requests and further work queues up in the kernel's buffers. When
+    /// shedding, requests that arrive while the service is not ready are
+    /// rejected immediately with a `Retry-After` header set to `retry_after`,
+    /// rounded up to whole seconds.
+    ///
+    /// By default load is not shed.
+    pub fn shed_load(&mut self, retry_after: Duration) -> &mut Self {
+        self.config.shed_load = Some(retry_after);
+        self
+    }
+
+    /// Serve every IO yielded by `incoming` via the server's hyper http
+    /// settings
+    ///
+    /// Each connection is spawned onto the default executor, and the returned
+    /// future completes once `incoming` ends. Errors from individual
+    /// connections are logged, while an error from `incoming` itself ends
+    /// the accept loop.
+    pub fn serve_incoming<St>(self, incoming: St) -> Incoming<St, S, B>
+    where
+        St: Stream,
+        St::Item: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        Incoming::new(incoming, self)
+    }
+
+    /// Serve the `io` stream via the server's hyper http settings
+    ///
+    /// These are hyper's defaults, unless the server was created by a
+    /// [`Builder`].
+    ///
+    /// [`Builder`]: ./struct.Builder.html
     pub fn serve<I>(&mut self, io: I) -> Serve<S::MakeError>
     where
         I: AsyncRead + AsyncWrite + Send + 'static,
     {
-        let http = Http::new();
+        let http = self.http.clone();
         self.serve_with(io, http)
     }
 
     /// Serve the `io` stream via the provided hyper http settings
+    ///
+    /// These replace the server's own settings, including those set by a
+    /// [`Builder`], for this connection.
+    ///
+    /// [`Builder`]: ./struct.Builder.html
     pub fn serve_with<I>(&mut self, io: I, http: Http) -> Serve<S::MakeError>
     where
         I: AsyncRead + AsyncWrite + Send + 'static,
     {
-        let fut = self
-            .maker
-            .make_service(())
-            .map_err(Error::MakeService)
-            .and_then(move |svc| {
-                let svc = LiftService::new(svc);
-                http.serve_connection(io, svc).map_err(Error::Protocol)
-            });
+        let conn = make_service(&mut self.maker).map(move |svc| (io, svc));
+        self.serve_connection(conn, http, None)
+    }
+
+    /// Serve the `io` stream of a client connected from `peer` via the
+    /// provided hyper http settings
+    ///
+    /// This behaves like `serve_with` but records the peer address on the
+    /// connection span when the `tracing` feature is enabled.
+    pub fn serve_with_peer<I>(&mut self, io: I, http: Http, peer: SocketAddr) -> Serve<S::MakeError>
+    where
+        I: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let conn = make_service(&mut self.maker).map(move |svc| (io, svc));
+        self.serve_connection(conn, http, Some(peer))
+    }
+
+    /// Serve the IO produced by `handshake` via the server's hyper http
+    /// settings
+    ///
+    /// This is meant for handshakes that run before HTTP, such as accepting
+    /// a TLS session. If the handshake fails, the connection ends with
+    /// `Error::Handshake`.
+    ///
+    /// The service for the connection is only made once the handshake
+    /// succeeded, from a clone of the `MakeService`.
+    pub fn serve_handshake<F>(&mut self, handshake: F) -> Serve<S::MakeError>
+    where
+        S: Clone,
+        F: Future + Send + 'static,
+        F::Item: AsyncRead + AsyncWrite + Send + 'static,
+        F::Error: Into<crate::Error>,
+    {
+        let mut maker = self.maker.clone();
+        let conn = handshake
+            .map_err(|e| {
+                let e: crate::Error = e.into();
+                event!(error = %e, "handshake error");
+                Error::Handshake(e)
+            })
+            .and_then(move |io| make_service(&mut maker).map(move |svc| (io, svc)));
+        let http = self.http.clone();
+        self.serve_connection(conn, http, None)
+    }
+
+    fn serve_connection<F, I>(
+        &mut self,
+        conn: F,
+        http: Http,
+        peer: Option<SocketAddr>,
+    ) -> Serve<S::MakeError>
+    where
+        F: Future<Item = (I, S::Service), Error = Error<S::MakeError>> + Send + 'static,
+        I: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let span = span!("connection", peer.addr = ?peer);
+        // Only referenced by the span when `tracing` is enabled.
+        let _ = peer;
+        let config = self.config.clone();
+
+        let fut = conn.and_then(move |(io, svc)| {
+            let protocol = |e| {
+                event!(error = %e, "connection error");
+                Error::serve(e)
+            };
+
+            if !config.has_timeouts() {
+                let svc = LiftService::new(svc, config, None);
+                let conn = http.serve_connection(io, svc).map_err(protocol);
+                return Either::A(conn);
+            }
+
+            let tracker = Tracker::new();
+            let io = TrackedIo::new(io, tracker.clone());
+            let header_read = config.header_read_timeout;
+            let idle = config.idle_timeout;
+
+            let svc = LiftService::new(svc, config, Some(tracker.clone()));
+            let conn = http.serve_connection(io, svc).map_err(protocol);
+            Either::B(Timeouts::new(conn, tracker, header_read, idle))
+        });
+
+        Box::new(Instrumented::new(fut, span))
+    }
+}
 
-        Box::new(fut)
+/// Make the service for a new connection.
+fn make_service<S, B>(maker: &mut S) -> impl Future<Item = S::Service, Error = Error<S::MakeError>>
+where
+    S: MakeService<(), Request<Body>, Response = Response<B>>,
+{
+    maker.make_service(()).map_err(|e| {
+        event!("error making service");
+        Error::MakeService(e)
+    })
+}
+
+impl Config {
+    fn has_timeouts(&self) -> bool {
+        self.header_read_timeout.is_some() || self.idle_timeout.is_some()
     }
 }
 
 impl<T, B> LiftService<T, B> {
-    pub(crate) fn new(inner: T) -> Self {
+    fn new(inner: T, config: Config, tracker: Option<Tracker>) -> Self {
         LiftService {
             inner,
+            config,
+            tracker,
+            ready: false,
             _pd: PhantomData,
         }
     }
@@ -110,19 +392,71 @@ where
     T::Error: Into<crate::Error>,
 {
     type ReqBody = hyper::Body;
-    type ResBody = LiftBody<B>;
+    type ResBody = ResponseBody<B>;
     type Error = crate::Error;
     type Future = LiftServiceFuture<T::Future, B>;
 
     fn poll_ready(&mut self) -> Poll<(), Self::Error> {
-        self.inner.poll_ready().map_err(Into::into)
+        if self.config.shed_load.is_none() {
+            return self.inner.poll_ready().map_err(Into::into);
+        }
+
+        // Always accept the next request, and decide in `call` whether it
+        // reaches the inner service.
+        self.ready = self.inner.poll_ready().map_err(Into::into)?.is_ready();
+        Ok(Async::Ready(()))
     }
 
     fn call(&mut self, request: Request<Self::ReqBody>) -> Self::Future {
-        let fut = self.inner.call(request.map(Body::from));
+        let span = span!(
+            "request",
+            method = %request.method(),
+            uri = %request.uri(),
+            version = ?request.version()
+        );
+
+        let in_flight = self.tracker.as_ref().map(Tracker::request);
+
+        let state = {
+            let _enter = span.enter();
+            let shed = self.config.shed_load.filter(|_| !self.ready);
+            // Every call must be preceded by a successful `poll_ready`.
+            self.ready = false;
+
+            match (shed, self.config.max_request_body_size) {
+                (Some(retry_after), _) => {
+                    event!("service not ready; shedding request");
+                    State::Local(Some(shed::service_unavailable(retry_after)))
+                }
+                (None, Some(max)) if limit::exceeds_limit(request.headers(), max) => {
+                    event!(limit = max, "request body too large");
+                    let response = limit::payload_too_large(request.version());
+                    State::Local(Some(response))
+                }
+                (None, max) => {
+                    let request = match max {
+                        // Bodies of a known length are already bounded by hyper.
+                        Some(max) if limit::content_length(request.headers()).is_none() => {
+                            request.map(|body| Body::wrap_stream(Limited::new(body, max)))
+                        }
+                        _ => request.map(Body::from),
+                    };
+
+                    let inner = &mut self.inner;
+                    match panic::catch(&self.config.panic_hook, || inner.call(request)) {
+                        Ok(fut) => State::Inner(fut),
+                        Err(_) => State::Local(Some(panic::internal_server_error())),
+                    }
+                }
+            }
+        };
 
         LiftServiceFuture {
-            inner: fut,
+            state,
+            in_flight,
+            panic_hook: self.config.panic_hook.clone(),
+            error_handler: self.config.error_handler.clone(),
+            span,
             _pd: PhantomData,
         }
     }
@@ -136,22 +470,42 @@ where
     B::Data: Send,
     B::Error: Into<crate::Error>,
 {
-    type Item = Response<LiftBody<B>>;
+    type Item = Response<ResponseBody<B>>;
     type Error = crate::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let response = try_ready!(self.inner.poll().map_err(Into::into));
-        Ok(response.map(LiftBody::from).into())
-    }
-}
+        let _enter = self.span.enter();
 
-impl<E: fmt::Debug> fmt::Display for Error<E> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match &self {
-            Error::Protocol(why) => f.debug_tuple("Protocol").field(why).finish(),
-            Error::MakeService(why) => f.debug_tuple("MakeService").field(why).finish(),
-        }
+        let response = match self.state {
+            State::Inner(ref mut fut) => match panic::catch(&self.panic_hook, || fut.poll()) {
+                Ok(Ok(Async::Ready(response))) => {
+                    response.map(|body| Kind::Lift(LiftBody::from(body)))
+                }
+                Ok(Ok(Async::NotReady)) => return Ok(Async::NotReady),
+                Ok(Err(e)) => {
+                    let e: crate::Error = e.into();
+                    event!(error = %e, "service error");
+
+                    let handled = self.error_handler.as_ref().and_then(|h| h.handle(&e));
+                    match handled {
+                        Some(response) => response.map(Kind::Local),
+                        None => return Err(e),
+                    }
+                }
+                Err(_) => panic::internal_server_error().map(Kind::Local),
+            },
+            State::Local(ref mut response) => response
+                .take()
+                .expect("polled after complete")
+                .map(Kind::Local),
+        };
+
+        event!(status = response.status().as_u16(), "response");
+
+        // The request stays in flight until hyper is done with the body.
+        let in_flight = self.in_flight.take();
+        let panic_hook = self.panic_hook.clone();
+        let response = response.map(|kind| ResponseBody::new(kind, in_flight, panic_hook));
+        Ok(response.into())
     }
 }
-
-impl<E: fmt::Debug> ::std::error::Error for Error<E> {}
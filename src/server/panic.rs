@@ -0,0 +1,91 @@
+use http::StatusCode;
+use hyper::{Body, Response};
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+
+/// A panic caught while calling the inner service or polling its response.
+///
+/// Passed to the hook registered with `Server::catch_panics`. When a panic
+/// happens while streaming a response body, it is also the error that the
+/// body fails with.
+#[derive(Debug)]
+pub struct Panic {
+    message: Option<String>,
+}
+
+/// The hook registered with `Server::catch_panics`.
+#[derive(Clone)]
+pub(super) struct PanicHook(Arc<dyn Fn(&Panic) + Send + Sync>);
+
+/// Run `f`, catching and reporting any panic if a hook is registered.
+pub(super) fn catch<F, T>(hook: &Option<PanicHook>, f: F) -> Result<T, Panic>
+where
+    F: FnOnce() -> T,
+{
+    let hook = match hook {
+        Some(hook) => hook,
+        None => return Ok(f()),
+    };
+
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        let panic = Panic::new(payload);
+        event!(message = ?panic.message(), "service panicked");
+        (hook.0)(&panic);
+        panic
+    })
+}
+
+/// Builds the `500 Internal Server Error` response sent after a panic.
+pub(super) fn internal_server_error() -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+    response
+}
+
+// ===== impl Panic =====
+
+impl Panic {
+    fn new(payload: Box<dyn Any + Send>) -> Self {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned());
+
+        Panic { message }
+    }
+
+    /// The panic message, if the panic was raised with a string.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+}
+
+impl fmt::Display for Panic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.message {
+            Some(message) => write!(f, "service panicked: {}", message),
+            None => f.write_str("service panicked"),
+        }
+    }
+}
+
+impl std::error::Error for Panic {}
+
+// ===== impl PanicHook =====
+
+impl PanicHook {
+    pub(super) fn new<F>(hook: F) -> Self
+    where
+        F: Fn(&Panic) + Send + Sync + 'static,
+    {
+        PanicHook(Arc::new(hook))
+    }
+}
+
+impl fmt::Debug for PanicHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("PanicHook")
+    }
+}
@@ -0,0 +1,22 @@
+use http::header::{HeaderValue, RETRY_AFTER};
+use http::StatusCode;
+use hyper::{Body, Response};
+use std::time::Duration;
+
+/// Builds the `503 Service Unavailable` response sent while shedding load.
+pub(super) fn service_unavailable(retry_after: Duration) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+
+    // `Retry-After` only has whole second precision, so round up to avoid
+    // asking clients to retry before the delay has passed.
+    let mut secs = retry_after.as_secs();
+    if retry_after.subsec_nanos() > 0 {
+        secs += 1;
+    }
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(secs));
+
+    response
+}
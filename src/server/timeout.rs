@@ -0,0 +1,240 @@
+use super::Error;
+use futures::{Async, Future, Poll};
+use log::debug;
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_timer::{clock, Delay};
+
+/// The HTTP/2 connection preface starts with these bytes.
+const H2_PREFACE: &[u8] = b"PRI";
+
+/// Tracks read activity and in-flight requests for a single connection.
+#[derive(Clone, Debug)]
+pub(super) struct Tracker {
+    activity: Arc<Mutex<Activity>>,
+}
+
+/// Marks a request as in flight until dropped.
+#[derive(Debug)]
+pub(super) struct InFlight {
+    tracker: Tracker,
+}
+
+/// An IO that reports reads to a `Tracker`.
+#[derive(Debug)]
+pub(super) struct TrackedIo<I> {
+    inner: I,
+    tracker: Tracker,
+}
+
+/// Drives a connection, failing it once a header or idle deadline passes.
+#[derive(Debug)]
+pub(super) struct Timeouts<F, E> {
+    inner: F,
+    tracker: Tracker,
+    header_read: Option<Duration>,
+    idle: Option<Duration>,
+    delay: Option<Delay>,
+    _pd: PhantomData<fn() -> E>,
+}
+
+#[derive(Debug)]
+struct Activity {
+    in_flight: usize,
+    /// When bytes were last read, or the last request in flight finished,
+    /// whichever is later. The connection is idle from then on.
+    last_active: Instant,
+    /// When the first byte of a request head was read, until that request
+    /// reaches the service.
+    head_started: Option<Instant>,
+    bytes_read: u64,
+    http2: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Expired {
+    HeaderRead,
+    Idle,
+}
+
+// ===== impl Tracker =====
+
+impl Tracker {
+    pub(super) fn new() -> Self {
+        let activity = Activity {
+            in_flight: 0,
+            last_active: clock::now(),
+            head_started: None,
+            bytes_read: 0,
+            http2: false,
+        };
+
+        Tracker {
+            activity: Arc::new(Mutex::new(activity)),
+        }
+    }
+
+    /// Record that a request head was fully read and handed to the service.
+    pub(super) fn request(&self) -> InFlight {
+        let mut activity = self.activity.lock().unwrap();
+        activity.in_flight += 1;
+        activity.head_started = None;
+
+        InFlight {
+            tracker: self.clone(),
+        }
+    }
+
+    fn read(&self, buf: &[u8]) {
+        let now = clock::now();
+        let mut activity = self.activity.lock().unwrap();
+
+        if activity.bytes_read == 0 && buf.starts_with(H2_PREFACE) {
+            activity.http2 = true;
+        }
+
+        activity.bytes_read += buf.len() as u64;
+        activity.last_active = now;
+
+        // With nothing in flight, bytes read are the start of the next
+        // request head. HTTP/2 frames its own headers, so only HTTP/1 is
+        // subject to the header read deadline.
+        if activity.in_flight == 0 && !activity.http2 && activity.head_started.is_none() {
+            activity.head_started = Some(now);
+        }
+    }
+
+    fn deadline(
+        &self,
+        header_read: Option<Duration>,
+        idle: Option<Duration>,
+    ) -> Option<(Instant, Expired)> {
+        let activity = self.activity.lock().unwrap();
+
+        if activity.in_flight > 0 {
+            return None;
+        }
+
+        let header_read = header_read
+            .and_then(|timeout| activity.head_started.map(|started| started + timeout))
+            .map(|at| (at, Expired::HeaderRead));
+        let idle = idle.map(|timeout| (activity.last_active + timeout, Expired::Idle));
+
+        match (header_read, idle) {
+            (Some(a), Some(b)) => Some(if a.0 <= b.0 { a } else { b }),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if let Ok(mut activity) = self.tracker.activity.lock() {
+            activity.in_flight -= 1;
+            if activity.in_flight == 0 {
+                activity.last_active = clock::now();
+            }
+        }
+    }
+}
+
+// ===== impl TrackedIo =====
+
+impl<I> TrackedIo<I> {
+    pub(super) fn new(inner: I, tracker: Tracker) -> Self {
+        TrackedIo { inner, tracker }
+    }
+}
+
+impl<I: Read> Read for TrackedIo<I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.tracker.read(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+impl<I: Write> Write for TrackedIo<I> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<I: AsyncRead> AsyncRead for TrackedIo<I> {}
+
+impl<I: AsyncWrite> AsyncWrite for TrackedIo<I> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.inner.shutdown()
+    }
+}
+
+// ===== impl Timeouts =====
+
+impl<F, E> Timeouts<F, E> {
+    pub(super) fn new(
+        inner: F,
+        tracker: Tracker,
+        header_read: Option<Duration>,
+        idle: Option<Duration>,
+    ) -> Self {
+        Timeouts {
+            inner,
+            tracker,
+            header_read,
+            idle,
+            delay: None,
+            _pd: PhantomData,
+        }
+    }
+}
+
+impl<F, E> Future for Timeouts<F, E>
+where
+    F: Future<Item = (), Error = Error<E>>,
+{
+    type Item = ();
+    type Error = Error<E>;
+
+    fn poll(&mut self) -> Poll<(), Self::Error> {
+        if let Async::Ready(()) = self.inner.poll()? {
+            return Ok(Async::Ready(()));
+        }
+
+        let (at, expired) = match self.tracker.deadline(self.header_read, self.idle) {
+            Some(deadline) => deadline,
+            None => return Ok(Async::NotReady),
+        };
+
+        let delay = self.delay.get_or_insert_with(|| Delay::new(at));
+        delay.reset(at);
+
+        match delay.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(())) => match expired {
+                Expired::HeaderRead => {
+                    event!("header read timed out");
+                    Err(Error::HeaderTimeout)
+                }
+                Expired::Idle => {
+                    event!("connection idle timed out");
+                    Err(Error::IdleTimeout)
+                }
+            },
+            Err(e) => {
+                // Without a timer the connection can still be served,
+                // it just won't be timed out.
+                debug!("connection timer error: {}", e);
+                Ok(Async::NotReady)
+            }
+        }
+    }
+}
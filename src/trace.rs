@@ -0,0 +1,77 @@
+//! Optional `tracing` instrumentation.
+//!
+//! When the `tracing` feature is disabled every span is a zero sized no-op
+//! and events compile to nothing, so call sites do not need to be gated.
+
+use futures::{Future, Poll};
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::Span;
+
+/// A no-op span used when the `tracing` feature is disabled.
+#[cfg(not(feature = "tracing"))]
+#[derive(Clone, Debug)]
+pub(crate) struct Span;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    pub(crate) fn none() -> Self {
+        Span
+    }
+
+    pub(crate) fn enter(&self) -> Entered {
+        Entered
+    }
+}
+
+/// Guard returned by the no-op `Span::enter`.
+#[cfg(not(feature = "tracing"))]
+#[derive(Debug)]
+pub(crate) struct Entered;
+
+/// Create a new `DEBUG` span, or a no-op span without the `tracing` feature.
+macro_rules! span {
+    ($name:expr) => {
+        span!($name,)
+    };
+    ($name:expr, $($field:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!($name, $($field)*);
+        #[cfg(not(feature = "tracing"))]
+        let span = $crate::trace::Span::none();
+        span
+    }};
+}
+
+/// Emit a `DEBUG` event inside the current span when `tracing` is enabled.
+macro_rules! event {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        {
+            tracing::debug!($($arg)*);
+        }
+    }};
+}
+
+/// A future that enters `span` every time it is polled.
+#[derive(Debug)]
+pub(crate) struct Instrumented<F> {
+    inner: F,
+    span: Span,
+}
+
+impl<F> Instrumented<F> {
+    pub(crate) fn new(inner: F, span: Span) -> Self {
+        Instrumented { inner, span }
+    }
+}
+
+impl<F: Future> Future for Instrumented<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let _enter = self.span.enter();
+        self.inner.poll()
+    }
+}
@@ -0,0 +1,181 @@
+use futures::sync::mpsc;
+use futures::{Future, Stream};
+use hyper::{Body, Request};
+use std::io;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+use tokio::timer::Delay;
+use tower_hyper::client::{Balance, Builder, Change, Connect, NoEndpoints};
+use tower_service::Service;
+use tower_util::Ready;
+
+mod support;
+use support::*;
+
+#[test]
+fn balances_across_endpoints() {
+    let mut rt = Runtime::new().unwrap();
+
+    let mut endpoints = Endpoints::new();
+    let counts = [endpoints.serve(&mut rt, "a"), endpoints.serve(&mut rt, "b")];
+    let mut balance =
+        Balance::from_targets(Connect::<_, Body, _, _>::new(endpoints), vec![0usize, 1]);
+
+    for _ in 0..20 {
+        balance = ready(&mut rt, balance);
+        let res = rt
+            .block_on(balance.call(Request::new(Body::empty())))
+            .unwrap();
+        assert_eq!(res.status(), http::StatusCode::OK);
+    }
+
+    assert!(counts[0].load(Ordering::SeqCst) > 0);
+    assert!(counts[1].load(Ordering::SeqCst) > 0);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn removes_failed_endpoints() {
+    let mut rt = Runtime::new().unwrap();
+
+    let mut endpoints = Endpoints::new();
+    endpoints.serve(&mut rt, "a");
+    // Connections to the second endpoint are refused.
+    endpoints.refuse("b");
+
+    let mut balance =
+        Balance::from_targets(Connect::<_, Body, _, _>::new(endpoints), vec![0usize, 1]);
+
+    balance = ready(&mut rt, balance);
+    assert_eq!(balance.len(), 1);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn applies_changes() {
+    let mut rt = Runtime::new().unwrap();
+
+    let mut endpoints = Endpoints::new();
+    let counts = [endpoints.serve(&mut rt, "a"), endpoints.serve(&mut rt, "b")];
+    let (tx, rx) = mpsc::unbounded();
+    let rx = rx.map_err(|()| io::Error::other("closed"));
+    let mut balance = Balance::new(Connect::<_, Body, _, _>::new(endpoints), rx);
+
+    tx.unbounded_send(Change::Insert(0usize)).unwrap();
+    tx.unbounded_send(Change::Insert(1)).unwrap();
+    balance = ready(&mut rt, balance);
+    assert_eq!(balance.len(), 2);
+
+    tx.unbounded_send(Change::Remove(0)).unwrap();
+    for _ in 0..5 {
+        balance = ready(&mut rt, balance);
+        rt.block_on(balance.call(Request::new(Body::empty())))
+            .unwrap();
+    }
+
+    assert_eq!(balance.len(), 1);
+    assert_eq!(counts[0].load(Ordering::SeqCst), 0);
+    assert_eq!(counts[1].load(Ordering::SeqCst), 5);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn fails_without_endpoints() {
+    let mut rt = Runtime::new().unwrap();
+
+    // Connections to the only endpoint are refused.
+    let mut endpoints = Endpoints::new();
+    endpoints.refuse("a");
+
+    let balance = Balance::from_targets(Connect::<_, Body, _, _>::new(endpoints), vec![0usize]);
+
+    let err = rt.block_on(Ready::new(balance).map(drop)).unwrap_err();
+    assert!(err.is::<NoEndpoints>());
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn replaces_retired_connections() {
+    let mut rt = Runtime::new().unwrap();
+
+    let mut endpoints = Endpoints::new();
+    let requests = endpoints.serve(&mut rt, "a");
+    let connects = endpoints.connects();
+    let connect = Connect::<_, Body, _, _>::new(endpoints).max_requests(1);
+    let mut balance = Balance::from_targets(connect, vec![0usize]);
+
+    for _ in 0..3 {
+        balance = ready(&mut rt, balance);
+        rt.block_on(balance.call(Request::new(Body::empty())))
+            .unwrap();
+    }
+
+    assert_eq!(balance.len(), 1);
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+    assert_eq!(connects.load(Ordering::SeqCst), 3);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn reconnects_closed_connections() {
+    let mut rt = Runtime::new().unwrap();
+
+    let mut endpoints = Endpoints::new();
+    let requests = endpoints.serve(&mut rt, "a");
+    let connects = endpoints.connects();
+    let mut balance = Balance::from_targets(Connect::<_, Body, _, _>::new(endpoints), vec![0usize]);
+
+    // Each connection closes gracefully after its only request.
+    for _ in 0..3 {
+        balance = ready(&mut rt, balance);
+        let req = Request::builder()
+            .header("connection", "close")
+            .body(Body::empty())
+            .unwrap();
+        rt.block_on(balance.call(req)).unwrap();
+    }
+
+    assert_eq!(balance.len(), 1);
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+    assert_eq!(connects.load(Ordering::SeqCst), 3);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn prefers_fewer_pending_requests() {
+    let mut rt = Runtime::new().unwrap();
+
+    // HTTP/2 connections stay ready with a request pending.
+    let mut endpoints = Endpoints::new();
+    let counts = [endpoints.serve(&mut rt, "a"), endpoints.serve(&mut rt, "b")];
+    let mut builder = Builder::new();
+    builder.http2_only(true);
+    let (tx, rx) = mpsc::unbounded();
+    let rx = rx.map_err(|()| io::Error::other("closed"));
+    let mut balance = Balance::new(
+        Connect::<_, Body, _, _>::with_builder(endpoints, builder),
+        rx,
+    );
+
+    // Keep a request to endpoint 0 pending.
+    tx.unbounded_send(Change::Insert(0usize)).unwrap();
+    balance = ready(&mut rt, balance);
+    let pending = balance.call(Request::new(Body::empty()));
+
+    // Give endpoint 1 time to be ready too.
+    tx.unbounded_send(Change::Insert(1)).unwrap();
+    balance = ready(&mut rt, balance);
+    let connected = Delay::new(Instant::now() + Duration::from_millis(50));
+    rt.block_on(connected).unwrap();
+
+    for _ in 0..5 {
+        balance = ready(&mut rt, balance);
+        rt.block_on(balance.call(Request::new(Body::empty())))
+            .unwrap();
+    }
+
+    assert_eq!(counts[1].load(Ordering::SeqCst), 5);
+    drop(pending);
+    rt.shutdown_now().wait().unwrap()
+}
@@ -0,0 +1,326 @@
+use bytes::Bytes;
+use futures::{future, stream, Async, Future, Poll, Stream};
+use hyper::body::Payload;
+use hyper::{Body, Chunk, Request, Response};
+use std::collections::VecDeque;
+use std::io::Cursor;
+use tokio::runtime::Runtime;
+use tokio_buf::SizeHint;
+use tower_hyper::body::{BoxBody, LiftBytes, ReplayError, Replayable};
+use tower_hyper::client::Connect;
+use tower_hyper::memory;
+use tower_hyper::server::Server;
+use tower_service::Service;
+use tower_util::MakeService;
+
+#[test]
+fn box_body_round_trip() {
+    let mut rt = Runtime::new().unwrap();
+
+    let (connector, listener) = memory::channel();
+    let mut server = Server::new(MakeEcho);
+    let serve = listener
+        .for_each(move |io| {
+            tokio::spawn(
+                server
+                    .serve(io)
+                    .map_err(|e| panic!("server error: {:?}", e)),
+            );
+            Ok(())
+        })
+        .map_err(|e| panic!("listener error: {}", e));
+    rt.spawn(serve);
+
+    let mut connect = Connect::new(connector);
+    let mut client = rt.block_on(connect.make_service(())).unwrap();
+
+    let chunks = stream::iter_ok::<_, std::io::Error>(vec!["hello", " ", "world"]);
+    let body = BoxBody::new(Body::wrap_stream(chunks));
+    let req = Request::post("/").body(body).unwrap();
+    let res = rt.block_on(client.call(req)).unwrap();
+    let body = rt.block_on(res.into_body().concat2()).unwrap();
+
+    assert_eq!(&body[..], b"hello world");
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn box_body_map_data() {
+    let mut rt = Runtime::new().unwrap();
+
+    let body = BoxBody::map_data(Body::from("hello"), |chunk| {
+        Chunk::from(chunk.to_ascii_uppercase())
+    });
+    let body = rt
+        .block_on(Body::wrap_stream(Chunks(body)).concat2())
+        .unwrap();
+
+    assert_eq!(&body[..], b"HELLO");
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn exact_size_hint_sets_content_length() {
+    let mut rt = Runtime::new().unwrap();
+
+    let (connector, listener) = memory::channel();
+    let mut server = Server::new(MakeEcho);
+    let serve = listener
+        .for_each(move |io| {
+            tokio::spawn(
+                server
+                    .serve(io)
+                    .map_err(|e| panic!("server error: {:?}", e)),
+            );
+            Ok(())
+        })
+        .map_err(|e| panic!("listener error: {}", e));
+    rt.spawn(serve);
+
+    let mut connect = Connect::new(connector);
+    let mut client = rt.block_on(connect.make_service(())).unwrap();
+
+    let body = BoxBody::new(Fixed(Some(Chunk::from("hello world"))));
+    let res = rt.block_on(client.call(Request::new(body))).unwrap();
+    assert_eq!(res.headers()["x-request-content-length"], "11");
+
+    let req = Request::get("/fixed").body(BoxBody::empty()).unwrap();
+    let res = rt.block_on(client.call(req)).unwrap();
+    assert_eq!(res.headers()["content-length"], "5");
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn lift_bytes_skips_consumed_data() {
+    // Large enough not to be stored inline, so that slicing keeps the pointer.
+    let bytes = Bytes::from(&b"hello world, this body is not stored inline"[..]);
+    let ptr = bytes.as_ptr();
+
+    let mut cursor = Cursor::new(bytes);
+    cursor.set_position(6);
+    let mut body = LiftBytes::from(Cursors(vec![cursor].into()));
+
+    match body.poll_data().unwrap() {
+        Async::Ready(Some(chunk)) => {
+            assert_eq!(&chunk[..], &b"world, this body is not stored inline"[..]);
+            assert_eq!(chunk.as_ptr(), ptr.wrapping_add(6));
+        }
+        _ => panic!("body ended early"),
+    }
+}
+
+#[test]
+fn lift_bytes_large_body() {
+    let mut rt = Runtime::new().unwrap();
+
+    let (io, server_io) = memory::duplex(64 * 1024);
+    let mut server = Server::new(MakeEcho);
+    rt.spawn(
+        server
+            .serve(server_io)
+            .map_err(|e| panic!("server error: {:?}", e)),
+    );
+
+    let handshake = hyper::client::conn::Builder::new().handshake::<_, LiftBytes<Cursors>>(io);
+    let (mut client, conn) = rt.block_on(handshake).unwrap();
+    rt.spawn(conn.map_err(|e| panic!("connection error: {}", e)));
+
+    let chunks = (0..256)
+        .map(|_| Bytes::from(vec![0; 64 * 1024]))
+        .collect::<Vec<_>>();
+    let req = Request::post("/")
+        .body(LiftBytes::from(Cursors::new(chunks)))
+        .unwrap();
+    let res = rt.block_on(client.send_request(req)).unwrap();
+    let body = rt.block_on(res.into_body().concat2()).unwrap();
+
+    assert_eq!(body.len(), 16 * 1024 * 1024);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn replay_buffered_body() {
+    let body = Replayable::new(hello_world(), 64);
+    let retry = body.replay().unwrap();
+
+    assert_eq!(read_to_end(body).unwrap(), b"hello world");
+    assert_eq!(
+        read_to_end(retry.replay().unwrap()).unwrap(),
+        b"hello world"
+    );
+    assert!(!http_body::Body::is_end_stream(&retry));
+    assert_eq!(read_to_end(retry).unwrap(), b"hello world");
+}
+
+#[test]
+fn replay_over_limit() {
+    let body = Replayable::new(hello_world(), 8);
+    let retry = body.replay().unwrap();
+
+    assert_eq!(read_to_end(body.replay().unwrap()).unwrap(), b"hello world");
+    assert!(!body.is_replayable());
+    assert!(body.replay().is_none());
+
+    let err = read_to_end(retry).unwrap_err();
+    assert_eq!(err.downcast_ref::<ReplayError>().unwrap().limit(), 8);
+}
+
+#[test]
+fn replay_after_end() {
+    let body = Replayable::new(Ended(false), 64);
+    let retry = body.replay().unwrap();
+
+    assert_eq!(read_to_end(body).unwrap(), b"");
+    // The copy caught up with the ended body without polling it again.
+    assert!(http_body::Body::is_end_stream(&retry));
+    assert_eq!(read_to_end(retry).unwrap(), b"");
+}
+
+/// A body of two chunks.
+fn hello_world() -> Body {
+    Body::wrap_stream(stream::iter_ok::<_, std::io::Error>(vec![
+        "hello", " world",
+    ]))
+}
+
+/// Reads a body that is always ready.
+fn read_to_end<B>(mut body: B) -> Result<Vec<u8>, B::Error>
+where
+    B: http_body::Body<Data = Chunk>,
+{
+    let mut buf = Vec::new();
+    loop {
+        match body.poll_data()? {
+            Async::Ready(Some(chunk)) => buf.extend_from_slice(&chunk),
+            Async::Ready(None) => return Ok(buf),
+            Async::NotReady => panic!("body not ready"),
+        }
+    }
+}
+
+/// An empty body that panics when polled for data after it ended.
+struct Ended(bool);
+impl http_body::Body for Ended {
+    type Data = Chunk;
+    type Error = hyper::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        assert!(!self.0, "polled after end");
+        self.0 = true;
+        Ok(None.into())
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        Ok(None.into())
+    }
+
+    fn is_end_stream(&self) -> bool {
+        false
+    }
+}
+
+/// A body yielding its chunks as `Cursor<Bytes>`.
+struct Cursors(VecDeque<Cursor<Bytes>>);
+impl Cursors {
+    fn new(chunks: Vec<Bytes>) -> Self {
+        Cursors(chunks.into_iter().map(Cursor::new).collect())
+    }
+}
+
+impl http_body::Body for Cursors {
+    type Data = Cursor<Bytes>;
+    type Error = hyper::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        Ok(self.0.pop_front().into())
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        Ok(None.into())
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// A body of a single chunk with an exact size hint.
+struct Fixed(Option<Chunk>);
+impl http_body::Body for Fixed {
+    type Data = Chunk;
+    type Error = hyper::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        Ok(self.0.take().into())
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        Ok(None.into())
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.0.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let mut hint = SizeHint::new();
+        let len = self.0.as_ref().map(|data| data.len()).unwrap_or(0) as u64;
+        hint.set_upper(len);
+        hint.set_lower(len);
+        hint
+    }
+}
+
+/// Streams the data of a `BoxBody`.
+struct Chunks(BoxBody);
+impl Stream for Chunks {
+    type Item = Chunk;
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        http_body::Body::poll_data(&mut self.0)
+    }
+}
+
+/// Echoes the request body back as a `BoxBody`, along with the request's
+/// `Content-Length` as `x-request-content-length`. Requests to `/fixed` are
+/// answered with a `Fixed` body instead.
+struct Echo;
+impl Service<Request<Body>> for Echo {
+    type Response = Response<BoxBody>;
+    type Error = hyper::Error;
+    type Future = future::FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        if req.uri().path() == "/fixed" {
+            let body = Fixed(Some(Chunk::from("hello")));
+            return future::ok(Response::new(BoxBody::new(body)));
+        }
+
+        let content_length = req.headers().get("content-length").cloned();
+        let mut res = Response::new(BoxBody::from(req.into_body()));
+        if let Some(value) = content_length {
+            res.headers_mut().insert("x-request-content-length", value);
+        }
+        future::ok(res)
+    }
+}
+
+struct MakeEcho;
+impl Service<()> for MakeEcho {
+    type Response = Echo;
+    type Error = hyper::Error;
+    type Future = future::FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, _: ()) -> Self::Future {
+        future::ok(Echo)
+    }
+}
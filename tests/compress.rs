@@ -0,0 +1,167 @@
+#![cfg(feature = "compression")]
+
+use flate2::read::GzDecoder;
+use futures::{future, Async, Future, Poll};
+use http::{header, Request, Response, StatusCode};
+use hyper::{Body, Chunk};
+use std::io::Read;
+use tower_hyper::server::Compress;
+use tower_service::Service;
+
+const TEXT: &str = "hello world, hello world, hello world, hello world";
+
+#[test]
+fn gzip() {
+    let mut svc = Compress::new(Mock("text/plain")).min_size(0);
+    let res = svc.call(request("gzip")).wait().unwrap();
+
+    assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+    assert_eq!(res.headers()[header::VARY], "accept-encoding");
+    assert!(res.headers().get(header::CONTENT_LENGTH).is_none());
+
+    let encoded = read_to_end(res.into_body());
+    let mut decoded = String::new();
+    GzDecoder::new(&encoded[..])
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, TEXT);
+}
+
+#[test]
+fn prefers_brotli() {
+    let mut svc = Compress::new(Mock("text/plain")).min_size(0);
+    let res = svc.call(request("gzip, br")).wait().unwrap();
+
+    assert_eq!(res.headers()[header::CONTENT_ENCODING], "br");
+
+    let encoded = read_to_end(res.into_body());
+    let mut decoded = String::new();
+    brotli::Decompressor::new(&encoded[..], 4096)
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, TEXT);
+}
+
+#[test]
+fn respects_quality() {
+    let mut svc = Compress::new(Mock("text/plain")).min_size(0);
+    let res = svc.call(request("br;q=0.5, gzip")).wait().unwrap();
+
+    assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+}
+
+#[test]
+fn wildcard_keeps_refused_codings() {
+    let mut svc = Compress::new(Mock("text/plain")).min_size(0);
+    let res = svc.call(request("br;q=0, *")).wait().unwrap();
+    assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+
+    let mut svc = Compress::new(Mock("text/plain")).min_size(0);
+    let res = svc.call(request("br;q=0, gzip;q=0, *")).wait().unwrap();
+    assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+}
+
+#[test]
+fn not_accepted() {
+    let mut svc = Compress::new(Mock("text/plain")).min_size(0);
+    let res = svc.call(Request::new(Body::empty())).wait().unwrap();
+
+    assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+    assert_eq!(res.headers()[header::VARY], "accept-encoding");
+    assert_eq!(read_to_end(res.into_body()), TEXT.as_bytes());
+}
+
+#[test]
+fn skips_small_bodies() {
+    let mut svc = Compress::new(Mock("text/plain"));
+    let res = svc.call(request("gzip")).wait().unwrap();
+
+    assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+    assert!(res.headers().get(header::VARY).is_none());
+}
+
+#[test]
+fn skips_compressed_content_types() {
+    let mut svc = Compress::new(Mock("image/png")).min_size(0);
+    let res = svc.call(request("gzip")).wait().unwrap();
+
+    assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+    assert_eq!(read_to_end(res.into_body()), TEXT.as_bytes());
+}
+
+#[test]
+fn skips_partial_content() {
+    let mut svc = Compress::new(Partial).min_size(0);
+    let res = svc.call(request("gzip")).wait().unwrap();
+
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+    assert_eq!(read_to_end(res.into_body()), TEXT.as_bytes());
+}
+
+fn request(accept_encoding: &'static str) -> Request<Body> {
+    Request::get("/")
+        .header(header::ACCEPT_ENCODING, accept_encoding)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Reads a body that is always ready.
+fn read_to_end<B>(mut body: B) -> Vec<u8>
+where
+    B: http_body::Body<Data = Chunk>,
+    B::Error: std::fmt::Debug,
+{
+    let mut buf = Vec::new();
+    loop {
+        match body.poll_data().unwrap() {
+            Async::Ready(Some(chunk)) => buf.extend_from_slice(&chunk),
+            Async::Ready(None) => return buf,
+            Async::NotReady => panic!("body not ready"),
+        }
+    }
+}
+
+/// Responds with `TEXT` and the given content type.
+struct Mock(&'static str);
+impl Service<Request<Body>> for Mock {
+    type Response = Response<Body>;
+    type Error = hyper::Error;
+    type Future = future::FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, _: Request<Body>) -> Self::Future {
+        let res = Response::builder()
+            .header(header::CONTENT_TYPE, self.0)
+            .header(header::CONTENT_LENGTH, TEXT.len().to_string())
+            .body(Body::from(TEXT))
+            .unwrap();
+        future::ok(res)
+    }
+}
+
+/// Responds with `TEXT` as a range of a larger representation.
+struct Partial;
+impl Service<Request<Body>> for Partial {
+    type Response = Response<Body>;
+    type Error = hyper::Error;
+    type Future = future::FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, _: Request<Body>) -> Self::Future {
+        let range = format!("bytes 0-{}/{}", TEXT.len() - 1, TEXT.len() * 2);
+        let res = Response::builder()
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(header::CONTENT_TYPE, "text/plain")
+            .header(header::CONTENT_RANGE, range)
+            .body(Body::from(TEXT))
+            .unwrap();
+        future::ok(res)
+    }
+}
@@ -1,15 +1,18 @@
-use futures::{Future, Poll};
+use futures::{future, Future, Poll, Stream as _};
 use http::{Uri, Version};
 use http_connection::HttpConnection;
 use hyper::client::connect::{Destination, HttpConnector};
 use hyper::{Body, Request};
 use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 use tokio::runtime::Runtime;
+use tokio::timer::Delay;
 use tokio_tcp::TcpStream;
-use tower_hyper::client::Connect;
+use tower_hyper::client::{Connect, Error, ResponseHeadTimedOut, RetryableError};
+use tower_hyper::memory;
 use tower_hyper::util::Connector;
 use tower_service::Service;
-use tower_util::MakeService;
+use tower_util::{MakeService, Ready};
 
 mod support;
 use support::*;
@@ -65,6 +68,130 @@ fn http_connection_http2() {
     rt.shutdown_now().wait().unwrap()
 }
 
+#[test]
+fn response_head_timeout() {
+    let mut rt = Runtime::new().unwrap();
+
+    // Accept connections but never answer on them.
+    let (connector, listener) = memory::channel();
+    rt.spawn(listener.collect().map(|_| ()).map_err(|_| ()));
+
+    let mut connect =
+        Connect::<_, Body, _, _>::new(connector).response_head_timeout(Duration::from_millis(50));
+    let mut client = rt.block_on(connect.make_service(())).unwrap();
+
+    let err = rt
+        .block_on(client.call(Request::new(Body::empty())))
+        .unwrap_err();
+    match err {
+        Error::Timeout(_) => {}
+        e => panic!("unexpected error: {}", e),
+    }
+
+    let err = rt
+        .block_on(future::poll_fn(move || client.poll_ready()))
+        .unwrap_err();
+    let err = match err {
+        Error::Timeout(source) => source,
+        e => panic!("unexpected error: {}", e),
+    };
+    let err = err.downcast_ref::<ResponseHeadTimedOut>().unwrap();
+    assert_eq!(err.timeout(), Duration::from_millis(50));
+
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn response_head_timeout_ignores_idle_connections() {
+    let mut rt = Runtime::new().unwrap();
+
+    let addr = next_addr();
+    rt.spawn(server(addr, false));
+
+    let connector = Connector::new(HttpConnector::new(1));
+    let mut connect = Connect::new(connector).response_head_timeout(Duration::from_millis(50));
+    let dst = Destination::try_from_uri(format!("http://{}", addr).parse().unwrap()).unwrap();
+    let client = rt.block_on(connect.make_service(dst)).unwrap();
+
+    let idle = Delay::new(Instant::now() + Duration::from_millis(200));
+    rt.block_on(idle).unwrap();
+
+    let mut client = rt.block_on(Ready::new(client)).unwrap();
+    let res = rt
+        .block_on(client.call(Request::new(Body::empty())))
+        .unwrap();
+    assert!(res.status().is_success());
+
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn connection_closed() {
+    let mut rt = Runtime::new().unwrap();
+
+    // Read the start of each request, then hang up without a response.
+    let (connector, listener) = memory::channel();
+    rt.spawn(
+        listener
+            .for_each(|io| {
+                let read = tokio::io::read(io, vec![0; 1024]);
+                tokio::spawn(read.map(|_| ()).map_err(|_| ()));
+                Ok(())
+            })
+            .map_err(|_| ()),
+    );
+
+    let mut connect = Connect::new(connector);
+    let mut client = rt.block_on(connect.make_service(())).unwrap();
+
+    let err = rt
+        .block_on(client.call(Request::new(Body::empty())))
+        .unwrap_err();
+    assert_eq!(err.to_string(), "connection closed");
+    match err {
+        Error::ConnectionClosed(_) => {}
+        e => panic!("unexpected error: {}", e),
+    }
+
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn background_error() {
+    let mut rt = Runtime::new().unwrap();
+
+    // Send something that is not HTTP before any request.
+    let (connector, listener) = memory::channel();
+    rt.spawn(
+        listener
+            .for_each(|io| {
+                let write = tokio::io::write_all(io, "garbage\r\n\r\n");
+                tokio::spawn(write.map(|_| ()).map_err(|_| ()));
+                Ok(())
+            })
+            .map_err(|_| ()),
+    );
+
+    let mut connect = Connect::<_, Body, _, _>::new(connector);
+    let mut client = rt.block_on(connect.make_service(())).unwrap();
+
+    // Give the background task time to read the garbage and fail.
+    let wait = Delay::new(Instant::now() + Duration::from_millis(100));
+    rt.block_on(wait).unwrap();
+
+    let err = rt
+        .block_on(future::poll_fn(move || client.poll_ready()))
+        .unwrap_err();
+    assert_eq!(err.to_string(), "connection background task failed");
+    assert!(err.is_unsent());
+    match err {
+        Error::Background(_) => {}
+        e => panic!("unexpected error: {}", e),
+    }
+
+    rt.shutdown_now().wait().unwrap()
+}
+
 struct Http2;
 
 struct Stream(TcpStream);
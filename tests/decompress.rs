@@ -0,0 +1,146 @@
+#![cfg(feature = "compression")]
+
+use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use futures::{future, Async, Future, Poll};
+use http::{header, Request, Response};
+use hyper::{Body, Chunk};
+use std::io::Write;
+use tower_hyper::client::{DecodedLengthError, Decompress};
+use tower_service::Service;
+
+#[test]
+fn gzip() {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(b"hello world").unwrap();
+    let encoded = encoder.finish().unwrap();
+
+    let mut svc = Decompress::new(Mock(Some("gzip"), encoded));
+    let res = svc.call(Request::new(Body::empty())).wait().unwrap();
+
+    assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+    assert!(res.headers().get(header::CONTENT_LENGTH).is_none());
+    assert_eq!(read_to_end(res.into_body()), b"hello world");
+}
+
+#[test]
+fn brotli() {
+    let mut encoded = Vec::new();
+    {
+        let mut encoder = brotli::CompressorWriter::new(&mut encoded, 4096, 5, 22);
+        encoder.write_all(b"hello world").unwrap();
+    }
+
+    let mut svc = Decompress::new(Mock(Some("br"), encoded));
+    let res = svc.call(Request::new(Body::empty())).wait().unwrap();
+
+    assert_eq!(read_to_end(res.into_body()), b"hello world");
+}
+
+#[test]
+fn deflate() {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(b"hello world").unwrap();
+    let encoded = encoder.finish().unwrap();
+
+    let mut svc = Decompress::new(Mock(Some("deflate"), encoded));
+    let res = svc.call(Request::new(Body::empty())).wait().unwrap();
+
+    assert_eq!(read_to_end(res.into_body()), b"hello world");
+}
+
+#[test]
+fn raw_deflate() {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(b"hello world").unwrap();
+    let encoded = encoder.finish().unwrap();
+
+    let mut svc = Decompress::new(Mock(Some("deflate"), encoded));
+    let res = svc.call(Request::new(Body::empty())).wait().unwrap();
+
+    assert_eq!(read_to_end(res.into_body()), b"hello world");
+}
+
+#[test]
+fn max_decoded_size() {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&vec![0; 1024 * 1024]).unwrap();
+    let encoded = encoder.finish().unwrap();
+
+    let mut svc = Decompress::new(Mock(Some("gzip"), encoded.clone())).max_decoded_size(1024);
+    let mut body = svc
+        .call(Request::new(Body::empty()))
+        .wait()
+        .unwrap()
+        .into_body();
+
+    let err = loop {
+        match http_body::Body::poll_data(&mut body) {
+            Ok(Async::Ready(Some(_))) => {}
+            Ok(Async::Ready(None)) => panic!("body decoded past the limit"),
+            Ok(Async::NotReady) => panic!("body not ready"),
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(
+        err.downcast_ref::<DecodedLengthError>().unwrap().limit(),
+        1024
+    );
+
+    let mut svc = Decompress::new(Mock(Some("gzip"), encoded)).max_decoded_size(1024 * 1024);
+    let res = svc.call(Request::new(Body::empty())).wait().unwrap();
+    assert_eq!(read_to_end(res.into_body()).len(), 1024 * 1024);
+}
+
+#[test]
+fn identity() {
+    let mut svc = Decompress::new(Mock(None, b"hello world".to_vec()));
+    let res = svc.call(Request::new(Body::empty())).wait().unwrap();
+
+    assert_eq!(res.headers()[header::CONTENT_LENGTH], "11");
+    assert_eq!(read_to_end(res.into_body()), b"hello world");
+}
+
+/// Reads a body that is always ready.
+fn read_to_end<B>(mut body: B) -> Vec<u8>
+where
+    B: http_body::Body<Data = Chunk>,
+    B::Error: std::fmt::Debug,
+{
+    let mut buf = Vec::new();
+    loop {
+        match body.poll_data().unwrap() {
+            Async::Ready(Some(chunk)) => buf.extend_from_slice(&chunk),
+            Async::Ready(None) => return buf,
+            Async::NotReady => panic!("body not ready"),
+        }
+    }
+}
+
+/// Responds with a body in the given encoding, after checking that the
+/// request accepts compressed responses.
+struct Mock(Option<&'static str>, Vec<u8>);
+impl Service<Request<Body>> for Mock {
+    type Response = Response<Body>;
+    type Error = hyper::Error;
+    type Future = future::FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        assert_eq!(req.headers()[header::ACCEPT_ENCODING], "gzip, deflate, br");
+
+        let body = self.1.clone();
+        let mut res = Response::new(Body::empty());
+        res.headers_mut()
+            .insert(header::CONTENT_LENGTH, body.len().into());
+        if let Some(encoding) = self.0 {
+            res.headers_mut()
+                .insert(header::CONTENT_ENCODING, encoding.parse().unwrap());
+        }
+        *res.body_mut() = Body::from(body);
+        future::ok(res)
+    }
+}
@@ -0,0 +1,109 @@
+use futures::sync::{mpsc, oneshot};
+use futures::{Future, Poll, Stream};
+use hyper::{Body, Request};
+use std::io;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Runtime;
+use tower_discover::Change as Discovered;
+use tower_hyper::client::{Change, Connect, ConnectDiscover};
+use tower_hyper::memory;
+use tower_service::Service;
+
+mod support;
+use support::*;
+
+#[test]
+fn inserts_and_removes_connections() {
+    let mut rt = Runtime::new().unwrap();
+
+    let mut endpoints = Endpoints::new();
+    endpoints.serve(&mut rt, "a");
+    let (tx, rx) = mpsc::unbounded();
+    let rx = rx.map_err(|()| io::Error::other("closed"));
+    let discover = ConnectDiscover::new(Connect::<_, Body, _, _>::new(endpoints), rx);
+
+    tx.unbounded_send(Change::Insert(0usize)).unwrap();
+    let (change, discover) = next_change(&mut rt, discover);
+    let mut connection = match change {
+        Discovered::Insert(0, connection) => connection,
+        _ => panic!("expected endpoint 0 to be inserted"),
+    };
+
+    let res = rt
+        .block_on(connection.call(Request::new(Body::empty())))
+        .unwrap();
+    assert_eq!(res.status(), http::StatusCode::OK);
+
+    tx.unbounded_send(Change::Remove(0)).unwrap();
+    match next_change(&mut rt, discover).0 {
+        Discovered::Remove(0) => {}
+        _ => panic!("expected endpoint 0 to be removed"),
+    }
+
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn skips_failed_connects() {
+    let mut rt = Runtime::new().unwrap();
+
+    let mut endpoints = Endpoints::new();
+    endpoints.serve(&mut rt, "a");
+    endpoints.refuse("b");
+    let (tx, rx) = mpsc::unbounded();
+    let rx = rx.map_err(|()| io::Error::other("closed"));
+    let discover = ConnectDiscover::new(Connect::<_, Body, _, _>::new(endpoints), rx);
+
+    // Endpoint 1 refuses connections.
+    tx.unbounded_send(Change::Insert(1usize)).unwrap();
+    tx.unbounded_send(Change::Insert(0)).unwrap();
+    match next_change(&mut rt, discover).0 {
+        Discovered::Insert(0, _) => {}
+        _ => panic!("expected endpoint 0 to be inserted"),
+    }
+
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn removing_cancels_connect() {
+    let mut rt = Runtime::new().unwrap();
+
+    let attempts = Arc::new(Mutex::new(Vec::new()));
+    let (tx, rx) = mpsc::unbounded();
+    let rx = rx.map_err(|()| io::Error::other("closed"));
+    let discover =
+        ConnectDiscover::new(Connect::<_, Body, _, _>::new(Stalled(attempts.clone())), rx);
+
+    tx.unbounded_send(Change::Insert(0usize)).unwrap();
+    let (poll, discover) = poll_discover(&mut rt, discover);
+    assert!(poll.unwrap().is_not_ready());
+    assert_eq!(attempts.lock().unwrap().len(), 1);
+    assert!(!attempts.lock().unwrap()[0].is_canceled());
+
+    tx.unbounded_send(Change::Remove(0)).unwrap();
+    let (poll, _discover) = poll_discover(&mut rt, discover);
+    assert!(poll.unwrap().is_not_ready());
+    assert!(attempts.lock().unwrap()[0].is_canceled());
+
+    rt.shutdown_now().wait().unwrap()
+}
+
+/// Never connects, keeping the sender of every attempt.
+struct Stalled(Arc<Mutex<Vec<oneshot::Sender<memory::Duplex>>>>);
+
+impl Service<usize> for Stalled {
+    type Response = memory::Duplex;
+    type Error = io::Error;
+    type Future = Box<dyn Future<Item = memory::Duplex, Error = io::Error> + Send>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, _: usize) -> Self::Future {
+        let (tx, rx) = oneshot::channel();
+        self.0.lock().unwrap().push(tx);
+        Box::new(rx.map_err(|_| io::Error::other("canceled")))
+    }
+}
@@ -0,0 +1,202 @@
+//! Measures how many bytes of a large body hyper copies before writing them
+//! to the connection, for `LiftBody` and for `LiftBytes`.
+//!
+//! The body is sent over an IO that checks whether each slice it is asked to
+//! write still points into one of the body's own buffers.
+
+use bytes::{Buf, Bytes};
+use futures::{future, Async, Future, Poll};
+use hyper::body::Payload;
+use hyper::client::conn::Builder;
+use hyper::Request;
+use iovec::IoVec;
+use std::collections::VecDeque;
+use std::io::{self, Cursor, Read, Write};
+use std::sync::{Arc, Mutex};
+use tokio::runtime::current_thread::Runtime;
+use tokio_buf::SizeHint;
+use tokio_io::{AsyncRead, AsyncWrite};
+use tower_hyper::body::{LiftBody, LiftBytes};
+
+/// 16 MiB in 64 KiB chunks.
+const CHUNKS: usize = 256;
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[test]
+fn lift_body_is_written_without_copies() {
+    let body = Cursors::new();
+    let chunks = body.chunks();
+    assert_eq!(copied_by_hyper(LiftBody::from(body), chunks), 0);
+}
+
+#[test]
+fn lift_bytes_is_written_without_copies() {
+    let body = Cursors::new();
+    let chunks = body.chunks();
+    assert_eq!(copied_by_hyper(LiftBytes::from(body), chunks), 0);
+}
+
+/// Sends `body` over an HTTP/1 connection and returns the number of its
+/// bytes that were not written from `chunks`, its original buffers.
+fn copied_by_hyper<B>(body: B, chunks: Vec<Bytes>) -> usize
+where
+    B: Payload,
+{
+    let mut rt = Runtime::new().unwrap();
+
+    let written = Arc::new(Mutex::new(Written::default()));
+    let io = Recorder {
+        chunks,
+        written: written.clone(),
+    };
+    let handshake = Builder::new().handshake::<_, B>(io);
+    let (mut client, mut conn) = rt.block_on(handshake).unwrap();
+
+    let req = Request::post("/").body(body).unwrap();
+    let _response = client.send_request(req);
+
+    // No response ever arrives, so the connection is only driven until the
+    // whole body was written.
+    let sent = future::poll_fn(|| -> Poll<(), hyper::Error> {
+        let _ = conn.poll()?;
+        let written = written.lock().unwrap();
+        if written.direct + written.copied < CHUNKS * CHUNK_SIZE {
+            return Ok(Async::NotReady);
+        }
+        Ok(Async::Ready(()))
+    });
+    rt.block_on(sent).unwrap();
+
+    let written = written.lock().unwrap();
+    written.copied
+}
+
+/// The body bytes written to a `Recorder`.
+#[derive(Default)]
+struct Written {
+    /// Whether the request head was written.
+    head: bool,
+    /// Bytes written straight from the body's buffers.
+    direct: usize,
+    /// Bytes written from anywhere else.
+    copied: usize,
+}
+
+/// An IO that accepts every write and never has anything to read.
+struct Recorder {
+    chunks: Vec<Bytes>,
+    written: Arc<Mutex<Written>>,
+}
+
+impl Recorder {
+    fn record(&self, slice: &[u8]) {
+        let mut written = self.written.lock().unwrap();
+
+        let mut body = slice;
+        if !written.head {
+            let end = slice
+                .windows(4)
+                .position(|window| window == b"\r\n\r\n")
+                .expect("request head is written first");
+            body = &slice[end + 4..];
+            written.head = true;
+        }
+
+        let start = body.as_ptr() as usize;
+        let is_direct = self.chunks.iter().any(|chunk| {
+            let chunk_start = chunk.as_ptr() as usize;
+            start >= chunk_start && start + body.len() <= chunk_start + chunk.len()
+        });
+        if is_direct {
+            written.direct += body.len();
+        } else {
+            written.copied += body.len();
+        }
+    }
+}
+
+impl Read for Recorder {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::WouldBlock.into())
+    }
+}
+
+impl AsyncRead for Recorder {}
+
+impl Write for Recorder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.record(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncWrite for Recorder {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn write_buf<B: Buf>(&mut self, buf: &mut B) -> Poll<usize, io::Error> {
+        let mut n = 0;
+        {
+            // `bytes_vec` overwrites these, `IoVec` has no empty default.
+            let placeholder: &IoVec = (&b"\0"[..]).into();
+            let mut slices = [placeholder; 32];
+            let count = buf.bytes_vec(&mut slices);
+            for slice in &slices[..count] {
+                self.record(slice);
+                n += slice.len();
+            }
+        }
+        buf.advance(n);
+        Ok(Async::Ready(n))
+    }
+}
+
+/// A body of `CHUNKS` chunks, yielded as `Cursor<Bytes>`.
+struct Cursors(VecDeque<Cursor<Bytes>>);
+impl Cursors {
+    fn new() -> Self {
+        let chunks = (0..CHUNKS)
+            .map(|_| Cursor::new(Bytes::from(vec![0; CHUNK_SIZE])))
+            .collect();
+        Cursors(chunks)
+    }
+
+    /// Handles to the buffers of the remaining chunks.
+    fn chunks(&self) -> Vec<Bytes> {
+        self.0
+            .iter()
+            .map(|cursor| cursor.get_ref().clone())
+            .collect()
+    }
+}
+
+impl http_body::Body for Cursors {
+    type Data = Cursor<Bytes>;
+    type Error = hyper::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        Ok(self.0.pop_front().into())
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        Ok(None.into())
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        // An exact length, so the body is sent without chunked framing.
+        let len = (self.0.len() * CHUNK_SIZE) as u64;
+        let mut hint = SizeHint::new();
+        hint.set_upper(len);
+        hint.set_lower(len);
+        hint
+    }
+}
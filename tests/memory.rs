@@ -0,0 +1,247 @@
+use futures::{future, Async, Future, Poll, Stream};
+use hyper::client::conn::Builder;
+use hyper::server::conn::Http;
+use hyper::{Body, Request, Response};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tower_hyper::client::{Connect, Error};
+use tower_hyper::memory;
+use tower_hyper::server::Server;
+use tower_service::Service;
+use tower_util::MakeService;
+
+#[test]
+fn http1() {
+    let mut rt = Runtime::new().unwrap();
+
+    let (connector, listener) = memory::channel();
+    rt.spawn(serve(listener, Http::new()));
+
+    let mut connect = Connect::new(connector);
+    let mut client = rt.block_on(connect.make_service(())).unwrap();
+
+    let fut = client.call(Request::new(Body::empty())).and_then(|res| {
+        assert_eq!(res.status(), http::StatusCode::OK);
+        assert_eq!(res.version(), http::Version::HTTP_11);
+        Ok(())
+    });
+
+    rt.block_on(fut).unwrap();
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn http2() {
+    let mut rt = Runtime::new().unwrap();
+
+    let (connector, listener) = memory::channel();
+    let mut http = Http::new();
+    http.http2_only(true);
+    rt.spawn(serve(listener, http));
+
+    let mut builder = Builder::new();
+    builder.http2_only(true);
+    let mut connect = Connect::with_builder(connector, builder);
+    let mut client = rt.block_on(connect.make_service(())).unwrap();
+
+    let fut = client.call(Request::new(Body::empty())).and_then(|res| {
+        assert_eq!(res.status(), http::StatusCode::OK);
+        assert_eq!(res.version(), http::Version::HTTP_2);
+        Ok(())
+    });
+
+    rt.block_on(fut).unwrap();
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn close_http1() {
+    let mut rt = Runtime::new().unwrap();
+
+    let (connector, listener) = memory::channel();
+    rt.spawn(serve(listener, Http::new()));
+
+    let mut connect = Connect::new(connector);
+    let mut client = rt.block_on(connect.make_service(())).unwrap();
+
+    // The response in flight is still received after closing.
+    let res = client.call(Request::new(Body::empty()));
+    let (res, ()) = rt.block_on(res.join(client.close())).unwrap();
+
+    assert_eq!(res.status(), http::StatusCode::OK);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn close_http2() {
+    let mut rt = Runtime::new().unwrap();
+
+    let (connector, listener) = memory::channel();
+    let mut http = Http::new();
+    http.http2_only(true);
+    rt.spawn(serve(listener, http));
+
+    let mut builder = Builder::new();
+    builder.http2_only(true);
+    let mut connect = Connect::with_builder(connector, builder);
+    let mut client = rt.block_on(connect.make_service(())).unwrap();
+
+    let res = client.call(Request::new(Body::empty()));
+    let (res, ()) = rt.block_on(res.join(client.close())).unwrap();
+
+    assert_eq!(res.status(), http::StatusCode::OK);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn max_requests() {
+    let mut rt = Runtime::new().unwrap();
+
+    let (connector, listener) = memory::channel();
+    rt.spawn(serve(listener, Http::new()));
+
+    let mut connect = Connect::<_, Body, _, _>::new(connector).max_requests(1);
+    let client = rt.block_on(connect.make_service(())).unwrap();
+
+    let (ready, mut client) = poll_ready(&mut rt, client);
+    assert!(ready.unwrap().is_ready());
+
+    let res = rt
+        .block_on(client.call(Request::new(Body::empty())))
+        .unwrap();
+    assert_eq!(res.status(), http::StatusCode::OK);
+
+    let (ready, client) = poll_ready(&mut rt, client);
+    match ready.unwrap_err() {
+        Error::Retired(_) => {}
+        e => panic!("unexpected error: {}", e),
+    }
+    assert!(client.is_retired());
+
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn max_lifetime() {
+    let mut rt = Runtime::new().unwrap();
+
+    let (connector, listener) = memory::channel();
+    rt.spawn(serve(listener, Http::new()));
+
+    let mut connect =
+        Connect::<_, Body, _, _>::new(connector).max_lifetime(Duration::from_millis(0));
+    let client = rt.block_on(connect.make_service(())).unwrap();
+
+    let (ready, client) = poll_ready(&mut rt, client);
+    match ready.unwrap_err() {
+        Error::Retired(_) => {}
+        e => panic!("unexpected error: {}", e),
+    }
+    assert!(client.is_retired());
+
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn max_lifetime_wakes_idle_connections() {
+    let mut rt = Runtime::new().unwrap();
+
+    let (connector, listener) = memory::channel();
+    rt.spawn(serve(listener, Http::new()));
+
+    let mut connect =
+        Connect::<_, Body, _, _>::new(connector).max_lifetime(Duration::from_millis(50));
+    let client = rt.block_on(connect.make_service(())).unwrap();
+
+    // Wait on the idle connection until it is retired, which only resolves
+    // if the lifetime timer wakes the task.
+    let mut client = Some(client);
+    let retired = future::poll_fn(move || match client.as_mut().unwrap().poll_ready() {
+        Ok(_) => Ok(Async::NotReady),
+        Err(e) => Ok::<_, ()>(Async::Ready((e, client.take().unwrap()))),
+    });
+    let (err, client) = rt.block_on(retired).unwrap();
+    match err {
+        Error::Retired(_) => {}
+        e => panic!("unexpected error: {}", e),
+    }
+    assert!(client.is_retired());
+
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn listener_dropped() {
+    let mut rt = Runtime::new().unwrap();
+
+    let (connector, listener) = memory::channel();
+    drop(listener);
+
+    let mut connect = Connect::<_, Body, _, _>::new(connector);
+    let res = rt.block_on(connect.make_service(()));
+
+    assert!(res.is_err());
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+#[should_panic(expected = "max_buf_size must be greater than zero")]
+fn zero_max_buf_size() {
+    memory::duplex(0);
+}
+
+/// Polls `client` for readiness once on `rt`, handing it back along with the
+/// result.
+fn poll_ready<S>(rt: &mut Runtime, mut client: S) -> (Poll<(), S::Error>, S)
+where
+    S: Service<Request<Body>> + Send + 'static,
+    S::Error: Send + 'static,
+{
+    let poll = future::lazy(move || Ok::<_, ()>((client.poll_ready(), client)));
+    rt.block_on(poll).unwrap()
+}
+
+fn serve(listener: memory::Listener, http: Http) -> impl Future<Item = (), Error = ()> {
+    let mut server = Server::new(MakeSvc);
+
+    listener
+        .for_each(move |io| {
+            tokio::spawn(
+                server
+                    .serve_with(io, http.clone())
+                    .map_err(|e| panic!("server error: {:?}", e)),
+            );
+            Ok(())
+        })
+        .map_err(|e| panic!("listener error: {}", e))
+}
+
+struct Svc;
+impl Service<Request<Body>> for Svc {
+    type Response = Response<Body>;
+    type Error = hyper::Error;
+    type Future = future::FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, _: Request<Body>) -> Self::Future {
+        future::ok(Response::new(Body::from("Hello World")))
+    }
+}
+
+struct MakeSvc;
+impl Service<()> for MakeSvc {
+    type Response = Svc;
+    type Error = hyper::Error;
+    type Future = future::FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, _: ()) -> Self::Future {
+        future::ok(Svc)
+    }
+}
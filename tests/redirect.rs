@@ -0,0 +1,199 @@
+use futures::{future, Async, Future, Poll};
+use http::{header, Method, Request, Response, StatusCode};
+use hyper::Body;
+use std::sync::{Arc, Mutex};
+use tower_hyper::body::Replayable;
+use tower_hyper::client::{FinalUri, Redirect, TooManyRedirects};
+use tower_service::Service;
+
+#[test]
+fn see_other_switches_to_get() {
+    let (svc, sent) = Mock::new();
+    let mut redirect = Redirect::new(svc);
+
+    let mut req = request(Method::POST, "http://example.com/see-other", "hello");
+    req.headers_mut()
+        .insert(header::CONTENT_LENGTH, "5".parse().unwrap());
+    let res = redirect.call(req).wait().unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.extensions().get::<FinalUri>().unwrap().uri(),
+        "http://example.com/done"
+    );
+
+    let sent = sent.lock().unwrap();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[1].method, Method::GET);
+    assert_eq!(sent[1].body, "");
+    assert!(sent[1].headers.get(header::CONTENT_LENGTH).is_none());
+}
+
+#[test]
+fn temporary_redirect_keeps_method_and_body() {
+    let (svc, sent) = Mock::new();
+    let mut redirect = Redirect::new(svc);
+
+    let req = request(Method::POST, "http://example.com/temporary", "hello");
+    let res = redirect.call(req).wait().unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let sent = sent.lock().unwrap();
+    assert_eq!(sent[1].method, Method::POST);
+    assert_eq!(sent[1].uri, "http://example.com/done");
+    assert_eq!(sent[1].body, "hello");
+}
+
+#[test]
+fn cross_origin_strips_credentials() {
+    let (svc, sent) = Mock::new();
+    let mut redirect = Redirect::new(svc);
+
+    let mut req = request(Method::GET, "http://example.com/cross", "");
+    req.headers_mut()
+        .insert(header::AUTHORIZATION, "secret".parse().unwrap());
+    redirect.call(req).wait().unwrap();
+
+    let sent = sent.lock().unwrap();
+    assert_eq!(sent[1].uri, "http://other.example.com/done");
+    assert!(sent[0].headers.get(header::AUTHORIZATION).is_some());
+    assert!(sent[1].headers.get(header::AUTHORIZATION).is_none());
+}
+
+#[test]
+fn too_many_redirects() {
+    let (svc, sent) = Mock::new();
+    let mut redirect = Redirect::new(svc).max_redirects(3);
+
+    let req = request(Method::GET, "http://example.com/loop", "");
+    let err = redirect.call(req).wait().unwrap_err();
+
+    assert_eq!(err.downcast_ref::<TooManyRedirects>().unwrap().max(), 3);
+    assert_eq!(sent.lock().unwrap().len(), 4);
+}
+
+#[test]
+fn max_redirects_zero_returns_redirect() {
+    let (svc, sent) = Mock::new();
+    let mut redirect = Redirect::new(svc).max_redirects(0);
+
+    let req = request(Method::GET, "http://example.com/loop", "");
+    let res = redirect.call(req).wait().unwrap();
+
+    assert_eq!(res.status(), StatusCode::FOUND);
+    assert_eq!(sent.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn resolves_locations() {
+    let cases = &[
+        (
+            "http://a.test/b/c",
+            "/r?u=http://x",
+            "http://a.test/r?u=http://x",
+        ),
+        ("http://a.test/b/c?q", "?x", "http://a.test/b/c?x"),
+        ("http://a.test/b/c/d", "../e", "http://a.test/b/e"),
+        ("http://a.test/b/c", "./../../e", "http://a.test/e"),
+        ("http://a.test/b/c", "e#frag", "http://a.test/b/e"),
+        (
+            "http://a.test/b",
+            "https://other.test/./e",
+            "https://other.test/e",
+        ),
+        ("https://a.test/b", "//other.test/e", "https://other.test/e"),
+        ("http://a.test", "e", "http://a.test/e"),
+    ];
+
+    for (base, location, expected) in cases {
+        let (svc, sent) = Mock::new();
+        let mut redirect = Redirect::new(svc);
+
+        let mut req = request(Method::GET, base, "");
+        req.headers_mut()
+            .insert("x-location", location.parse().unwrap());
+        redirect.call(req).wait().unwrap();
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent[1].uri, *expected, "{} + {}", base, location);
+    }
+}
+
+fn request(method: Method, uri: &str, body: &'static str) -> Request<Replayable<Body>> {
+    let body = Replayable::new(Body::from(body), 64);
+    let mut req = Request::new(body);
+    *req.method_mut() = method;
+    *req.uri_mut() = uri.parse().unwrap();
+    req
+}
+
+/// A request as seen by `Mock`.
+struct Sent {
+    method: Method,
+    uri: http::Uri,
+    headers: http::HeaderMap,
+    body: String,
+}
+
+/// Redirects requests depending on their path, and records every request.
+/// The first request is redirected to its `x-location` header, if any.
+#[derive(Clone)]
+struct Mock {
+    sent: Arc<Mutex<Vec<Sent>>>,
+}
+
+impl Mock {
+    fn new() -> (Self, Arc<Mutex<Vec<Sent>>>) {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        (Mock { sent: sent.clone() }, sent)
+    }
+}
+
+impl Service<Request<Replayable<Body>>> for Mock {
+    type Response = Response<Body>;
+    type Error = hyper::Error;
+    type Future = future::FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, req: Request<Replayable<Body>>) -> Self::Future {
+        let first = self.sent.lock().unwrap().is_empty();
+        let forced = match req.headers().get("x-location") {
+            Some(value) if first => Some(value.to_str().unwrap().to_string()),
+            _ => None,
+        };
+
+        let (status, location) = match (&forced, req.uri().path()) {
+            (Some(location), _) => (StatusCode::FOUND, location.as_str()),
+            (None, "/see-other") => (StatusCode::SEE_OTHER, "/done"),
+            (None, "/temporary") => (StatusCode::TEMPORARY_REDIRECT, "done"),
+            (None, "/cross") => (StatusCode::FOUND, "//other.example.com/done"),
+            (None, "/loop") => (StatusCode::FOUND, "/loop"),
+            (None, _) => (StatusCode::OK, ""),
+        };
+
+        let (parts, mut body) = req.into_parts();
+        let mut buf = Vec::new();
+        while let Async::Ready(Some(chunk)) = http_body::Body::poll_data(&mut body).unwrap() {
+            buf.extend_from_slice(&chunk);
+        }
+
+        self.sent.lock().unwrap().push(Sent {
+            method: parts.method,
+            uri: parts.uri,
+            headers: parts.headers,
+            body: String::from_utf8(buf).unwrap(),
+        });
+
+        let mut res = Response::new(Body::empty());
+        *res.status_mut() = status;
+        if !location.is_empty() {
+            res.headers_mut()
+                .insert(header::LOCATION, location.parse().unwrap());
+        }
+        future::ok(res)
+    }
+}
@@ -0,0 +1,103 @@
+use futures::Async;
+use http::{Method, Request};
+use hyper::Body;
+use std::time::Duration;
+use tower_hyper::body::Replayable;
+use tower_hyper::client::{RetryPolicy, RetryableError};
+use tower_retry::budget::Budget;
+use tower_retry::Policy;
+
+type Req = Request<Replayable<Body>>;
+
+#[test]
+fn retries_unsent_requests() {
+    let policy = RetryPolicy::new(budget());
+    let req = request(Method::POST);
+
+    assert!(retry(&policy, &req, Err(&Failed { unsent: true })));
+    assert!(!retry(&policy, &req, Err(&Failed { unsent: false })));
+}
+
+#[test]
+fn retries_idempotent_requests() {
+    let policy = RetryPolicy::new(budget());
+    let req = request(Method::PUT);
+
+    assert!(retry(&policy, &req, Err(&Failed { unsent: false })));
+}
+
+#[test]
+fn does_not_retry_responses() {
+    let policy = RetryPolicy::new(budget());
+    let req = request(Method::GET);
+
+    assert!(!retry(&policy, &req, Ok(&())));
+}
+
+#[test]
+fn budget_limits_retries() {
+    let policy = RetryPolicy::new(Budget::new(Duration::from_secs(10), 0, 0.0));
+    let req = request(Method::GET);
+
+    assert!(!retry(&policy, &req, Err(&Failed { unsent: true })));
+}
+
+#[test]
+fn does_not_retry_unreplayable_body() {
+    let policy = RetryPolicy::new(budget());
+    let body = Replayable::new(Body::from("hello world"), 5);
+    let req = Request::get("/").body(body).unwrap();
+
+    // Stream the body past its buffer limit.
+    let mut body = clone(&policy, &req).unwrap().into_body();
+    while let Async::Ready(Some(_)) = http_body::Body::poll_data(&mut body).unwrap() {}
+
+    assert!(clone(&policy, &req).is_none());
+    assert!(!retry(&policy, &req, Err(&Failed { unsent: true })));
+}
+
+#[test]
+fn clone_request() {
+    let policy = RetryPolicy::new(budget());
+    let body = Replayable::new(Body::from("hello world"), 64);
+    let req = Request::put("http://example.com/foo")
+        .header("x-foo", "bar")
+        .body(body)
+        .unwrap();
+
+    let clone = clone(&policy, &req).unwrap();
+
+    assert_eq!(clone.method(), Method::PUT);
+    assert_eq!(clone.uri(), "http://example.com/foo");
+    assert_eq!(clone.headers()["x-foo"], "bar");
+}
+
+fn budget() -> Budget {
+    Budget::new(Duration::from_secs(10), 10, 0.2)
+}
+
+fn request(method: Method) -> Req {
+    let body = Replayable::new(Body::empty(), 64);
+    let mut req = Request::new(body);
+    *req.method_mut() = method;
+    req
+}
+
+fn clone(policy: &RetryPolicy, req: &Req) -> Option<Req> {
+    Policy::<Req, (), Failed>::clone_request(policy, req)
+}
+
+fn retry(policy: &RetryPolicy, req: &Req, result: Result<&(), &Failed>) -> bool {
+    policy.retry(req, result).is_some()
+}
+
+/// An error that knows whether its request was sent.
+struct Failed {
+    unsent: bool,
+}
+
+impl RetryableError for Failed {
+    fn is_unsent(&self) -> bool {
+        self.unsent
+    }
+}
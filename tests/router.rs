@@ -0,0 +1,122 @@
+use futures::Future;
+use hyper::{Body, Request};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tower_hyper::client::{Connect, Router};
+use tower_service::Service;
+
+mod support;
+use support::*;
+
+#[test]
+fn routes_by_authority() {
+    let mut rt = Runtime::new().unwrap();
+    let endpoints = endpoints(&mut rt);
+    let connects = endpoints.connects();
+    let mut router = Router::new(Connect::new(endpoints));
+
+    for host in &["a.test", "b.test", "a.test"] {
+        let req = Request::get(format!("http://{}/", host))
+            .body(Body::empty())
+            .unwrap();
+        let res = rt.block_on(router.call(req)).unwrap();
+        assert_eq!(res.headers()["x-server"], *host);
+    }
+
+    // The second request to `a.test` reuses its connection.
+    assert_eq!(connects.load(Ordering::SeqCst), 2);
+
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn sets_host_header() {
+    let mut rt = Runtime::new().unwrap();
+    let mut router = Router::new(Connect::new(endpoints(&mut rt)));
+
+    let req = Request::get("http://a.test:8080/path")
+        .body(Body::empty())
+        .unwrap();
+    let res = rt.block_on(router.call(req)).unwrap();
+    assert_eq!(res.headers()["x-host"], "a.test:8080");
+
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn sends_origin_form() {
+    let mut rt = Runtime::new().unwrap();
+    let mut router = Router::new(Connect::new(endpoints(&mut rt)));
+
+    let req = Request::get("http://a.test/path?query")
+        .body(Body::empty())
+        .unwrap();
+    let res = rt.block_on(router.call(req)).unwrap();
+    assert_eq!(res.headers()["x-uri"], "/path?query");
+
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn evicts_idle_connections() {
+    let mut rt = Runtime::new().unwrap();
+    let endpoints = endpoints(&mut rt);
+    let connects = endpoints.connects();
+    let mut router = Router::new(Connect::new(endpoints)).idle_timeout(Duration::from_millis(0));
+
+    for host in &["a.test", "b.test", "a.test"] {
+        let req = Request::get(format!("http://{}/", host))
+            .body(Body::empty())
+            .unwrap();
+        rt.block_on(router.call(req)).unwrap();
+    }
+
+    // Routing to `b.test` evicted the idle connection to `a.test`.
+    assert_eq!(connects.load(Ordering::SeqCst), 3);
+
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn reconnects_closed_connections() {
+    let mut rt = Runtime::new().unwrap();
+    let endpoints = endpoints(&mut rt);
+    let connects = endpoints.connects();
+    let mut router = Router::new(Connect::new(endpoints));
+
+    // Each connection closes gracefully after its only request.
+    for _ in 0..2 {
+        let req = Request::get("http://a.test/")
+            .header("connection", "close")
+            .body(Body::empty())
+            .unwrap();
+        rt.block_on(router.call(req)).unwrap();
+    }
+
+    assert_eq!(connects.load(Ordering::SeqCst), 2);
+
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn relative_uri_error() {
+    let mut rt = Runtime::new().unwrap();
+    let endpoints = endpoints(&mut rt);
+    let connects = endpoints.connects();
+    let mut router = Router::new(Connect::new(endpoints));
+
+    let req = Request::get("/path").body(Body::empty()).unwrap();
+    rt.block_on(router.call(req)).unwrap_err();
+    assert_eq!(connects.load(Ordering::SeqCst), 0);
+
+    rt.shutdown_now().wait().unwrap()
+}
+
+/// Serve one in-memory endpoint per host.
+fn endpoints(rt: &mut Runtime) -> Endpoints {
+    let mut endpoints = Endpoints::new();
+    endpoints.serve(rt, "a.test");
+    endpoints.serve(rt, "b.test");
+    endpoints
+}
@@ -0,0 +1,615 @@
+use futures::future::{self, Either};
+use futures::{stream, Async, Future, Poll, Stream};
+use http::StatusCode;
+use hyper::{Body, Request, Response};
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+use tokio::timer::Delay;
+use tower::ServiceExt;
+use tower_hyper::client::{Connect, Connection};
+use tower_hyper::memory;
+use tower_hyper::server::{Builder, Error, LengthLimitError, LimitStrategy, Server};
+use tower_service::Service;
+use tower_util::MakeService;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[test]
+fn body_within_limit() {
+    let mut rt = Runtime::new().unwrap();
+
+    let mut server = Server::new(MakeSvc);
+    server.max_request_body_size(16);
+    let mut client = connect(&mut rt, server);
+
+    let req = Request::post("/").body(Body::from("hello world")).unwrap();
+    let res = rt.block_on(client.call(req)).unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn content_length_over_limit() {
+    let mut rt = Runtime::new().unwrap();
+
+    let mut server = Server::new(MakeSvc);
+    server.max_request_body_size(5);
+    let mut client = connect(&mut rt, server);
+
+    let req = Request::post("/")
+        .header("content-length", "11")
+        .body(Body::from("hello world"))
+        .unwrap();
+    let res = rt.block_on(client.call(req)).unwrap();
+
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn chunked_body_over_limit() {
+    let mut rt = Runtime::new().unwrap();
+
+    let mut server = Server::new(MakeSvc);
+    server.max_request_body_size(5);
+    let mut client = connect(&mut rt, server);
+
+    let chunks = stream::iter_ok::<_, std::io::Error>(vec!["hello", " ", "world"]);
+    let req = Request::post("/").body(Body::wrap_stream(chunks)).unwrap();
+    let res = rt.block_on(client.call(req)).unwrap();
+
+    // The service observed the `LengthLimitError` while reading the body.
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn header_read_timeout() {
+    let mut rt = Runtime::new().unwrap();
+
+    let mut server = Server::new(MakeSvc);
+    server.header_read_timeout(Duration::from_millis(100));
+
+    // Only send part of a request head and keep the connection open.
+    let (mut client, io) = memory::duplex(1024);
+    client.write_all(b"GET / HTTP/1.1\r\nhost: ").unwrap();
+
+    match rt.block_on(server.serve(io)) {
+        Err(Error::HeaderTimeout) => {}
+        res => panic!("expected header timeout, got {:?}", res),
+    }
+
+    drop(client);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn idle_timeout() {
+    let mut rt = Runtime::new().unwrap();
+
+    let mut server = Server::new(MakeSvc);
+    server.idle_timeout(Duration::from_millis(100));
+
+    let (client, io) = memory::duplex(1024);
+
+    match rt.block_on(server.serve(io)) {
+        Err(Error::IdleTimeout) => {}
+        res => panic!("expected idle timeout, got {:?}", res),
+    }
+
+    drop(client);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn idle_timeout_after_slow_request() {
+    let mut rt = Runtime::new().unwrap();
+
+    let mut server = Server::new(MakeSvc);
+    server.idle_timeout(Duration::from_millis(100));
+    let mut client = connect(&mut rt, server);
+
+    // The handler takes longer than the idle timeout.
+    let req = Request::get("/slow").body(Body::empty()).unwrap();
+    let res = rt.block_on(client.call(req)).unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // The idle clock restarted once the response was done, so the
+    // connection is still open for the next request.
+    let mut client = rt.block_on(client.ready()).unwrap();
+    let res = rt
+        .block_on(client.call(Request::new(Body::empty())))
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn connection_limit_pauses_accept() {
+    let mut rt = Runtime::new().unwrap();
+
+    let (connector, listener) = memory::channel();
+    let mut server = Server::new(MakeSvc);
+    server.max_connections(1);
+    rt.spawn(
+        server
+            .serve_incoming(listener)
+            .map_err(|e| panic!("listener error: {}", e)),
+    );
+
+    let mut connect = Connect::new(connector);
+    let mut first = rt.block_on(connect.make_service(())).unwrap();
+    let mut second = rt.block_on(connect.make_service(())).unwrap();
+
+    let res = rt
+        .block_on(first.call(Request::new(Body::empty())))
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // The second connection is not accepted while the first is open.
+    let timeout = Delay::new(Instant::now() + Duration::from_millis(100));
+    let pending = match rt.block_on(second.call(Request::new(Body::empty())).select2(timeout)) {
+        Ok(Either::B((_, pending))) => pending,
+        _ => panic!("second connection was served while at the limit"),
+    };
+
+    drop(first);
+
+    let res = rt.block_on(pending).unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn connection_limit_rejects() {
+    let mut rt = Runtime::new().unwrap();
+
+    let (connector, listener) = memory::channel();
+    let mut server = Server::new(MakeSvc);
+    server
+        .max_connections(1)
+        .limit_strategy(LimitStrategy::Reject);
+    rt.spawn(
+        server
+            .serve_incoming(listener)
+            .map_err(|e| panic!("listener error: {}", e)),
+    );
+
+    let mut connect = Connect::new(connector);
+    let mut first = rt.block_on(connect.make_service(())).unwrap();
+    let res = rt
+        .block_on(first.call(Request::new(Body::empty())))
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let mut second = rt.block_on(connect.make_service(())).unwrap();
+    let res = rt.block_on(second.call(Request::new(Body::empty())));
+    assert!(res.is_err());
+
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn catch_panics() {
+    let mut rt = Runtime::new().unwrap();
+
+    let panics = Arc::new(AtomicUsize::new(0));
+    let counter = panics.clone();
+
+    let mut server = Server::new(MakeSvc);
+    server.catch_panics(move |panic| {
+        assert_eq!(panic.message(), Some("boom"));
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+    let mut client = connect(&mut rt, server);
+
+    let req = Request::get("/panic").body(Body::empty()).unwrap();
+    let res = rt.block_on(client.call(req)).unwrap();
+
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(panics.load(Ordering::SeqCst), 1);
+
+    // The connection survives the panic.
+    let mut client = rt.block_on(client.ready()).unwrap();
+    let req = Request::get("/").body(Body::empty()).unwrap();
+    let res = rt.block_on(client.call(req)).unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn catch_panics_polling_response() {
+    let mut rt = Runtime::new().unwrap();
+
+    let panics = Arc::new(AtomicUsize::new(0));
+    let counter = panics.clone();
+
+    let mut server = Server::new(MakeSvc);
+    server.catch_panics(move |panic| {
+        assert_eq!(panic.message(), Some("boom"));
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+    let mut client = connect(&mut rt, server);
+
+    let req = Request::get("/panic-poll").body(Body::empty()).unwrap();
+    let res = rt.block_on(client.call(req)).unwrap();
+
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(panics.load(Ordering::SeqCst), 1);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn catch_panics_streaming_body() {
+    let mut rt = Runtime::new().unwrap();
+
+    let panics = Arc::new(AtomicUsize::new(0));
+    let counter = panics.clone();
+
+    let mut server = Server::new(MakeSvc);
+    server.catch_panics(move |panic| {
+        assert_eq!(panic.message(), Some("boom"));
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+    let mut client = connect(&mut rt, server);
+
+    let req = Request::get("/panic-body").body(Body::empty()).unwrap();
+    let res = rt.block_on(client.call(req)).unwrap();
+
+    // The head was already sent, so the body is aborted instead.
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(rt.block_on(res.into_body().concat2()).is_err());
+    assert_eq!(panics.load(Ordering::SeqCst), 1);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn service_error_without_handler() {
+    let mut rt = Runtime::new().unwrap();
+
+    let mut client = connect(&mut rt, Server::new(MakeSvc));
+
+    let req = Request::get("/error").body(Body::empty()).unwrap();
+    let res = rt.block_on(client.call(req));
+
+    assert!(res.is_err());
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn service_error_with_handler() {
+    let mut rt = Runtime::new().unwrap();
+
+    let mut server = Server::new(MakeSvc);
+    server.error_handler(|e: &(dyn std::error::Error + Send + Sync + 'static)| {
+        assert_eq!(e.to_string(), "unavailable");
+
+        let mut res = Response::new(Body::empty());
+        *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        Some(res)
+    });
+    let mut client = connect(&mut rt, server);
+
+    let req = Request::get("/error").body(Body::empty()).unwrap();
+    let res = rt.block_on(client.call(req)).unwrap();
+
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn service_error_classified() {
+    let mut rt = Runtime::new().unwrap();
+
+    let mut server = Server::new(MakeSvc);
+    let (mut client, io) = memory::duplex(1024);
+    client
+        .write_all(b"GET /error HTTP/1.1\r\nhost: example.com\r\n\r\n")
+        .unwrap();
+
+    match rt.block_on(server.serve(io)) {
+        Err(ref e @ Error::Service(_)) => assert!(!e.is_benign()),
+        res => panic!("expected service error, got {:?}", res),
+    }
+
+    drop(client);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn handshake_error() {
+    let mut rt = Runtime::new().unwrap();
+
+    let made = Arc::new(AtomicUsize::new(0));
+    let mut server = Server::new(CountingMakeSvc(made.clone()));
+    let handshake = future::err::<memory::Duplex, _>(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "bad handshake",
+    ));
+
+    match rt.block_on(server.serve_handshake(handshake)) {
+        Err(e @ Error::Handshake(_)) => {
+            assert_eq!(e.to_string(), "handshake failed: bad handshake");
+            assert!(std::error::Error::source(&e).is_some());
+        }
+        res => panic!("expected handshake error, got {:?}", res),
+    }
+    assert_eq!(made.load(Ordering::SeqCst), 0);
+
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn handshake_makes_service() {
+    let mut rt = Runtime::new().unwrap();
+
+    let made = Arc::new(AtomicUsize::new(0));
+    let mut server = Server::new(CountingMakeSvc(made.clone()));
+
+    let (io, server_io) = memory::duplex(1024);
+    rt.spawn(
+        server
+            .serve_handshake(future::ok::<_, std::io::Error>(server_io))
+            .map_err(|_| ()),
+    );
+
+    let (mut client, conn) = rt.block_on(hyper::client::conn::handshake(io)).unwrap();
+    rt.spawn(conn.map_err(|_| ()));
+
+    let res = rt
+        .block_on(client.send_request(Request::new(Body::empty())))
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(made.load(Ordering::SeqCst), 1);
+
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn shed_load() {
+    let mut rt = Runtime::new().unwrap();
+
+    let mut server = Server::new(MakeUnready);
+    server.shed_load(Duration::from_millis(1500));
+
+    let (io, server_io) = memory::duplex(1024);
+    rt.spawn(server.serve(server_io).map_err(|_| ()));
+
+    let (mut client, conn) = rt.block_on(hyper::client::conn::handshake(io)).unwrap();
+    rt.spawn(conn.map_err(|_| ()));
+
+    let res = rt
+        .block_on(client.send_request(Request::new(Body::empty())))
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(res.headers()["retry-after"], "2");
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn builder_validates_settings() {
+    let res = Builder::new()
+        .http2_initial_stream_window_size(1 << 31)
+        .build::<_, Body>(MakeSvc);
+    assert!(res.is_err());
+
+    let res = Builder::new()
+        .http2_initial_connection_window_size(1024)
+        .build::<_, Body>(MakeSvc);
+    assert!(res.is_err());
+
+    let res = Builder::new()
+        .http1_only(true)
+        .http2_only(true)
+        .build::<_, Body>(MakeSvc);
+    assert!(res.is_err());
+}
+
+#[test]
+fn builder_http2_settings() {
+    let mut rt = Runtime::new().unwrap();
+
+    let mut server = Builder::new()
+        .http2_only(true)
+        .http2_initial_stream_window_size(1024 * 1024)
+        .http2_max_concurrent_streams(10)
+        .build::<_, Body>(MakeSvc)
+        .unwrap();
+
+    let (connector, listener) = memory::channel();
+    let serve = listener
+        .for_each(move |io| {
+            tokio::spawn(server.serve(io).map_err(|_| ()));
+            Ok(())
+        })
+        .map_err(|e| panic!("listener error: {}", e));
+    rt.spawn(serve);
+
+    let mut builder = tower_hyper::client::Builder::new();
+    builder.http2_only(true);
+    let mut connect = Connect::with_builder(connector, builder);
+    let mut client = rt.block_on(connect.make_service(())).unwrap();
+
+    let res = rt
+        .block_on(client.call(Request::new(Body::empty())))
+        .unwrap();
+    assert_eq!(res.version(), http::Version::HTTP_2);
+    rt.shutdown_now().wait().unwrap()
+}
+
+#[test]
+fn serve_incoming_uses_builder_settings() {
+    let mut rt = Runtime::new().unwrap();
+
+    let server = Builder::new()
+        .http2_only(true)
+        .build::<_, Body>(MakeSvc)
+        .unwrap();
+
+    let (connector, listener) = memory::channel();
+    rt.spawn(
+        server
+            .serve_incoming(listener)
+            .map_err(|e| panic!("listener error: {}", e)),
+    );
+
+    // An HTTP/1 client is refused by the HTTP/2 only server.
+    let mut connect = Connect::new(connector);
+    let mut client = rt.block_on(connect.make_service(())).unwrap();
+    let res = rt.block_on(client.call(Request::new(Body::empty())));
+    assert!(res.is_err());
+    rt.shutdown_now().wait().unwrap()
+}
+
+fn connect(rt: &mut Runtime, mut server: Server<MakeSvc, Body>) -> Connection<Body> {
+    let (connector, listener) = memory::channel();
+
+    let serve = listener
+        .for_each(move |io| {
+            tokio::spawn(server.serve(io).map_err(|_| ()));
+            Ok(())
+        })
+        .map_err(|e| panic!("listener error: {}", e));
+    rt.spawn(serve);
+
+    let mut connect = Connect::new(connector);
+    rt.block_on(connect.make_service(())).unwrap()
+}
+
+/// Reads the whole request body before responding. Panics if the request
+/// path is `/panic`, fails if it is `/error` and responds after 300ms if it
+/// is `/slow`. The response future panics for `/panic-poll`, and the
+/// response body panics after its head is sent for `/panic-body`.
+struct Svc;
+impl Service<Request<Body>> for Svc {
+    type Response = Response<Body>;
+    type Error = BoxError;
+    type Future = Box<dyn Future<Item = Self::Response, Error = Self::Error> + Send>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        if req.uri().path() == "/panic" {
+            panic!("boom");
+        }
+
+        if req.uri().path() == "/panic-poll" {
+            return Box::new(future::lazy(|| -> Result<Self::Response, Self::Error> {
+                panic!("boom")
+            }));
+        }
+
+        if req.uri().path() == "/panic-body" {
+            let mut polled = false;
+            let body = stream::poll_fn(move || -> Poll<Option<hyper::Chunk>, BoxError> {
+                if polled {
+                    panic!("boom");
+                }
+                // Yield once, so the response head is flushed first.
+                polled = true;
+                futures::task::current().notify();
+                Ok(Async::NotReady)
+            });
+            return Box::new(future::ok(Response::new(Body::wrap_stream(body))));
+        }
+
+        if req.uri().path() == "/error" {
+            return Box::new(future::err("unavailable".into()));
+        }
+
+        if req.uri().path() == "/slow" {
+            let fut = Delay::new(Instant::now() + Duration::from_millis(300))
+                .map(|()| Response::new(Body::empty()))
+                .map_err(Into::into);
+            return Box::new(fut);
+        }
+
+        let fut = req.into_body().concat2().then(|res| {
+            let status = match res {
+                Ok(_) => StatusCode::OK,
+                Err(e) => match e.into_cause() {
+                    Some(ref cause) if cause.is::<LengthLimitError>() => StatusCode::BAD_REQUEST,
+                    _ => StatusCode::INTERNAL_SERVER_ERROR,
+                },
+            };
+
+            let mut res = Response::new(Body::empty());
+            *res.status_mut() = status;
+            Ok::<_, BoxError>(res)
+        });
+
+        Box::new(fut)
+    }
+}
+
+struct MakeSvc;
+impl Service<()> for MakeSvc {
+    type Response = Svc;
+    type Error = hyper::Error;
+    type Future = future::FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, _: ()) -> Self::Future {
+        future::ok(Svc)
+    }
+}
+
+/// Counts the services it makes.
+#[derive(Clone)]
+struct CountingMakeSvc(Arc<AtomicUsize>);
+impl Service<()> for CountingMakeSvc {
+    type Response = Svc;
+    type Error = hyper::Error;
+    type Future = future::FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, _: ()) -> Self::Future {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        future::ok(Svc)
+    }
+}
+
+/// Never becomes ready.
+struct Unready;
+impl Service<Request<Body>> for Unready {
+    type Response = Response<Body>;
+    type Error = BoxError;
+    type Future = future::FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(Async::NotReady)
+    }
+
+    fn call(&mut self, _: Request<Body>) -> Self::Future {
+        unreachable!("called while not ready")
+    }
+}
+
+struct MakeUnready;
+impl Service<()> for MakeUnready {
+    type Response = Unready;
+    type Error = hyper::Error;
+    type Future = future::FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, _: ()) -> Self::Future {
+        future::ok(Unready)
+    }
+}
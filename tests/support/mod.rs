@@ -1,13 +1,25 @@
-use futures::Future;
+// Each test only uses some of the helpers.
+#![allow(dead_code)]
+
+use futures::{future, try_ready, Async, Future, Poll, Stream};
+use hyper::client::connect::Destination;
 use hyper::service::service_fn_ok;
-use hyper::{Body, Response, Server};
+use hyper::{Body, Request, Response};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::{fmt, io};
+use tokio::runtime::Runtime;
+use tower_discover::{Change, Discover};
+use tower_hyper::memory;
+use tower_hyper::server::Server;
+use tower_service::Service;
+use tower_util::Ready;
 
 pub fn server(addr: SocketAddr, http2_only: bool) -> impl Future<Item = (), Error = ()> {
     let make_service = || service_fn_ok(|_req| Response::new(Body::from("Hello World")));
 
-    Server::bind(&addr)
+    hyper::Server::bind(&addr)
         .http2_only(http2_only)
         .serve(make_service)
         .map_err(|e| panic!("{}", e))
@@ -18,3 +30,166 @@ pub fn next_addr() -> SocketAddr {
     let port = NEXT_PORT.fetch_add(1, Ordering::AcqRel) as u16;
     SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port)
 }
+
+/// A change yielded by `D`.
+pub type DiscoverChange<D> = Change<<D as Discover>::Key, <D as Discover>::Service>;
+
+/// Waits on `rt` until `svc` is ready, handing it back.
+pub fn ready<S>(rt: &mut Runtime, svc: S) -> S
+where
+    S: Service<Request<Body>> + Send + 'static,
+    S::Error: fmt::Debug + Send + 'static,
+{
+    rt.block_on(Ready::new(svc)).unwrap()
+}
+
+/// Polls `discover` once on `rt`, handing it back along with the result.
+pub fn poll_discover<D>(rt: &mut Runtime, mut discover: D) -> (Poll<DiscoverChange<D>, D::Error>, D)
+where
+    D: Discover + Send + 'static,
+    D::Key: Send + 'static,
+    D::Service: Send + 'static,
+    D::Error: Send + 'static,
+{
+    let poll = future::lazy(move || Ok::<_, ()>((discover.poll(), discover)));
+    rt.block_on(poll).unwrap()
+}
+
+/// Waits on `rt` for the next change from `discover`, handing it back along
+/// with the change.
+pub fn next_change<D>(rt: &mut Runtime, discover: D) -> (DiscoverChange<D>, D)
+where
+    D: Discover + Send + 'static,
+    D::Key: Send + 'static,
+    D::Service: Send + 'static,
+    D::Error: fmt::Debug + Send + 'static,
+{
+    let mut discover = Some(discover);
+    let change = future::poll_fn(move || -> Poll<_, D::Error> {
+        let change = try_ready!(discover.as_mut().expect("polled after ready").poll());
+        Ok(Async::Ready((change, discover.take().unwrap())))
+    });
+    rt.block_on(change).unwrap()
+}
+
+/// Connects to named in-memory endpoints, by their index or by the host of
+/// a `Destination`, counting the connections made.
+#[derive(Default)]
+pub struct Endpoints {
+    endpoints: Vec<(&'static str, memory::Connector)>,
+    connects: Arc<AtomicUsize>,
+}
+
+impl Endpoints {
+    pub fn new() -> Self {
+        Endpoints::default()
+    }
+
+    /// Serve an endpoint, returning the count of requests it receives.
+    ///
+    /// Responses have an `x-server` header with the endpoint name, an
+    /// `x-uri` header with the request URI, and an `x-host` header with the
+    /// `Host` of the request.
+    pub fn serve(&mut self, rt: &mut Runtime, name: &'static str) -> Arc<AtomicUsize> {
+        let (connector, listener) = memory::channel();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let mut server = Server::new(MakeSvc(name, requests.clone()));
+
+        rt.spawn(
+            listener
+                .for_each(move |io| {
+                    tokio::spawn(server.serve(io).map_err(|_| ()));
+                    Ok(())
+                })
+                .map_err(|_| ()),
+        );
+
+        self.endpoints.push((name, connector));
+        requests
+    }
+
+    /// Add an endpoint that refuses connections.
+    pub fn refuse(&mut self, name: &'static str) {
+        let (connector, listener) = memory::channel();
+        drop(listener);
+        self.endpoints.push((name, connector));
+    }
+
+    /// The count of connections made to any endpoint.
+    pub fn connects(&self) -> Arc<AtomicUsize> {
+        self.connects.clone()
+    }
+}
+
+impl Service<usize> for Endpoints {
+    type Response = memory::Duplex;
+    type Error = io::Error;
+    type Future = future::FutureResult<memory::Duplex, io::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, target: usize) -> Self::Future {
+        self.connects.fetch_add(1, Ordering::SeqCst);
+        self.endpoints[target].1.call(())
+    }
+}
+
+impl Service<Destination> for Endpoints {
+    type Response = memory::Duplex;
+    type Error = io::Error;
+    type Future = future::FutureResult<memory::Duplex, io::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, dst: Destination) -> Self::Future {
+        self.connects.fetch_add(1, Ordering::SeqCst);
+        let connector = self
+            .endpoints
+            .iter_mut()
+            .find(|(name, _)| *name == dst.host())
+            .map(|(_, connector)| connector)
+            .expect("unknown host");
+        connector.call(())
+    }
+}
+
+struct Svc(&'static str, Arc<AtomicUsize>);
+impl Service<Request<Body>> for Svc {
+    type Response = Response<Body>;
+    type Error = hyper::Error;
+    type Future = future::FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        self.1.fetch_add(1, Ordering::SeqCst);
+        let mut res = Response::builder();
+        res.header("x-server", self.0);
+        res.header("x-uri", req.uri().to_string().as_str());
+        if let Some(host) = req.headers().get("host") {
+            res.header("x-host", host.clone());
+        }
+        future::ok(res.body(Body::empty()).unwrap())
+    }
+}
+
+struct MakeSvc(&'static str, Arc<AtomicUsize>);
+impl Service<()> for MakeSvc {
+    type Response = Svc;
+    type Error = hyper::Error;
+    type Future = future::FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, _: ()) -> Self::Future {
+        future::ok(Svc(self.0, self.1.clone()))
+    }
+}
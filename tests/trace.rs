@@ -0,0 +1,162 @@
+#![cfg(feature = "tracing")]
+
+use futures::{future, Future, Poll, Stream};
+use hyper::server::conn::Http;
+use hyper::{Body, Request, Response};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::current_thread::Runtime;
+use tower_hyper::client::Connect;
+use tower_hyper::memory;
+use tower_hyper::server::Server;
+use tower_service::Service;
+use tower_util::MakeService;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+#[test]
+fn spans() {
+    let recorder = Recorder::default();
+    let spans = recorder.spans.clone();
+
+    tracing::subscriber::with_default(recorder, || {
+        // A current thread runtime, so every span is created on the thread
+        // with the default subscriber.
+        let mut rt = Runtime::new().unwrap();
+
+        let (connector, listener) = memory::channel();
+        let mut server = Server::new(MakeSvc);
+        rt.spawn(
+            listener
+                .for_each(move |io| {
+                    tokio::runtime::current_thread::spawn(server.serve(io).map_err(|_| ()));
+                    Ok(())
+                })
+                .map_err(|_| ()),
+        );
+
+        let mut connect = Connect::new(connector);
+        let mut client = rt.block_on(connect.make_service(())).unwrap();
+        let res = rt
+            .block_on(client.call(Request::new(Body::empty())))
+            .unwrap();
+        assert_eq!(res.status(), http::StatusCode::OK);
+    });
+
+    let spans = spans.lock().unwrap();
+    for name in &["connect", "connection", "request"] {
+        assert!(
+            spans.contains(name),
+            "missing span {:?} in {:?}",
+            name,
+            spans
+        );
+    }
+    // One request span for the client and one for the server.
+    assert_eq!(spans.iter().filter(|name| **name == "request").count(), 2);
+}
+
+#[test]
+fn connection_peer() {
+    let recorder = Recorder::default();
+    let fields = recorder.fields.clone();
+    let peer = "127.0.0.1:4321".parse().unwrap();
+
+    tracing::subscriber::with_default(recorder, || {
+        let mut rt = Runtime::new().unwrap();
+
+        let (connector, listener) = memory::channel();
+        let mut server = Server::new(MakeSvc);
+        rt.spawn(
+            listener
+                .for_each(move |io| {
+                    let serve = server.serve_with_peer(io, Http::new(), peer);
+                    tokio::runtime::current_thread::spawn(serve.map_err(|_| ()));
+                    Ok(())
+                })
+                .map_err(|_| ()),
+        );
+
+        let mut connect = Connect::new(connector);
+        let mut client = rt.block_on(connect.make_service(())).unwrap();
+        rt.block_on(client.call(Request::new(Body::empty())))
+            .unwrap();
+    });
+
+    let fields = fields.lock().unwrap();
+    assert!(
+        fields.contains(&"peer.addr=Some(127.0.0.1:4321)".to_string()),
+        "missing peer address in {:?}",
+        fields
+    );
+}
+
+/// Records the names and fields of the spans created.
+#[derive(Default)]
+struct Recorder {
+    spans: Arc<Mutex<Vec<&'static str>>>,
+    fields: Arc<Mutex<Vec<String>>>,
+}
+
+/// Formats each recorded field as `name=value`.
+struct Fields<'a>(&'a mut Vec<String>);
+
+impl<'a> Visit for Fields<'a> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.push(format!("{}={:?}", field.name(), value));
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes) -> Id {
+        let mut spans = self.spans.lock().unwrap();
+        spans.push(span.metadata().name());
+        span.record(&mut Fields(&mut self.fields.lock().unwrap()));
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, _: &Id, _: &Record) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event) {}
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+struct Svc;
+impl Service<Request<Body>> for Svc {
+    type Response = Response<Body>;
+    type Error = hyper::Error;
+    type Future = future::FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, _: Request<Body>) -> Self::Future {
+        future::ok(Response::new(Body::empty()))
+    }
+}
+
+struct MakeSvc;
+impl Service<()> for MakeSvc {
+    type Response = Svc;
+    type Error = hyper::Error;
+    type Future = future::FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, _: ()) -> Self::Future {
+        future::ok(Svc)
+    }
+}